@@ -32,7 +32,7 @@ fn main() {
 
     if let Some(extension) = file_path.extension() {
         if extension == "adt" {
-            let adt = files::ADT::from_file(file_path, &chunks::wdt::MPHDFlags{ has_height_texturing: false }).unwrap();
+            let adt = files::ADT::from_file(file_path, &chunks::wdt::MPHDFlags::default()).unwrap();
             println!("{:#?}", adt);
         } else if extension == "wdt" {
             if let (Some(x), Some(y)) = (cli.x, cli.y) {
@@ -40,7 +40,9 @@ fn main() {
 
                 for c in adt.mcnk.iter() {
                     if Some(c.x) == cli.chunk_x && Some(c.y) == cli.chunk_y {
-                        println!("{:?}", &c.holes_low_res);
+                        for row in c.holes() {
+                            println!("{}", row.iter().map(|hole| if *hole { 'X' } else { '.' }).collect::<String>());
+                        }
                         
                         if cli.save_alphas {
                             for (i, data) in c.mcal.layers.iter().enumerate() {
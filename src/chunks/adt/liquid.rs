@@ -0,0 +1,55 @@
+/// The kind of a liquid, which decides how it is rendered and which vertex data it has.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LiquidKind {
+    Water,
+    Ocean,
+    Magma,
+    Slime,
+}
+
+impl LiquidKind {
+    /// The kind of a LiquidType.dbc entry in the stock 3.3.5 DBC, with any other entry treated as water.
+    ///
+    /// MH2O vertex formats are guessed from this too, see [`crate::chunks::adt::LiquidVertexFormat::from_liquid_type`].
+    pub fn from_liquid_type(liquid_type: u16) -> Self {
+        match liquid_type {
+            2 | 6 | 10 | 14 => LiquidKind::Ocean,
+            4 | 8 | 12 | 20 | 21 => LiquidKind::Slime,
+            3 | 7 | 11 | 15 | 19 | 121 | 141 | 181 => LiquidKind::Magma,
+            _ => LiquidKind::Water,
+        }
+    }
+}
+
+/// A rendered tile of a [`Liquid`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LiquidTile {
+    /// Whether the tile can be fished in, from the MH2O fishable mask, or `None` for MCLQ,
+    /// which has no documented fishable flag.
+    pub fishable: Option<bool>,
+    /// Swimming in the tile drains fatigue (deep water).
+    pub fatigue: bool,
+}
+
+/// A liquid covering (part of) an MCNK, the same whether it was read from MCLQ or MH2O.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Liquid {
+    pub kind: LiquidKind,
+    /// The LiquidType.dbc entry, which only MH2O stores.
+    pub liquid_type: Option<u16>,
+    pub min_height: f32,
+    pub max_height: f32,
+    /// The 9x9 vertex heights, row by row. Vertices without a height of their own are at `min_height`.
+    pub heights: [[f32; 9]; 9],
+    /// The 9x9 vertex depths, row by row, or 0 for liquids without depths (magma and slime).
+    pub depths: [[u8; 9]; 9],
+    /// The 8x8 tiles, row by row, or `None` where the liquid isn't rendered.
+    pub tiles: [[Option<LiquidTile>; 8]; 8],
+}
+
+impl Liquid {
+    /// The tile at (x, y) in the 8x8 grid of the MCNK, if the liquid is rendered there.
+    pub fn tile(&self, x: usize, y: usize) -> Option<LiquidTile> {
+        self.tiles.get(y).and_then(|row| row.get(x)).copied().flatten()
+    }
+}
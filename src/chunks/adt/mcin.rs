@@ -1,5 +1,9 @@
+use std::io::Write;
+
 use binread::BinRead;
 
+use crate::chunks::shared;
+
 #[derive(Clone, Debug, PartialEq, BinRead)]
 #[br(little)]
 pub struct MCINChunk {
@@ -13,16 +17,49 @@ pub struct MCINChunk {
         uint32_t asyncId;            // not in the adt file. client use only
     };
     */
-    offset: u32,
-    size: u32,
+    /// Offset of the MCNK chunk header, from the start of the file.
+    pub offset: u32,
+    /// Size of the MCNK chunk, including its header.
+    pub size: u32,
     flags: u32,
+    async_id: u32,
+}
+
+impl shared::ChunkWrite for MCINChunk {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&self.offset.to_le_bytes())?;
+        writer.write_all(&self.size.to_le_bytes())?;
+        writer.write_all(&self.flags.to_le_bytes())?;
+        writer.write_all(&self.async_id.to_le_bytes())
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, BinRead)]
 #[br(little)]
 pub struct MCIN {
     #[br(count = 16*16)]
-    chunks: Vec<MCINChunk>,
+    pub chunks: Vec<MCINChunk>,
+}
+
+impl MCIN {
+    /// The entry for the MCNK at (x, y) in the 16x16 grid of the ADT.
+    pub fn entry(&self, x: u32, y: u32) -> Option<&MCINChunk> {
+        if x >= 16 || y >= 16 {
+            return None;
+        }
+
+        self.chunks.get((y * 16 + x) as usize)
+    }
+}
+
+impl shared::ChunkWrite for MCIN {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        for chunk in &self.chunks {
+            chunk.write_data(writer)?;
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -30,7 +67,7 @@ mod tests {
     use super::*;
     use binread::BinReaderExt;
 
-    const RAW_MCIN: [u8; 12] = [ 0xFA, 0x10, 0x00, 0x00, 0xCC, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00 ];
+    const RAW_MCIN: [u8; 16] = [ 0xFA, 0x10, 0x00, 0x00, 0xCC, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00 ];
 
     #[test]
     fn parse_valid_chunk() {
@@ -40,6 +77,7 @@ mod tests {
             offset: 4346,
             size: 1740,
             flags: 0,
+            async_id: 0,
         })
     }
 }
\ No newline at end of file
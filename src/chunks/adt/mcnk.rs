@@ -1,29 +1,62 @@
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{Read, Seek, SeekFrom, Write};
 
 use bitvec::prelude::*;
 use binread::{BinRead, ReadOptions, BinResult, BinReaderExt};
 
-use crate::chunks::shared;
+use crate::chunks::adt::{Liquid, LiquidKind, LiquidTile};
+use crate::chunks::shared::{self, ChunkWrite};
+use crate::chunks::wdt::MPHDFlags;
+use crate::version::ClientVersion;
 
 const MCNK_FLAG_HAS_MCSH: u32 = 0x01;
 const MCNK_FLAG_IMPASS: u32 = 0x02;
 const MCNK_FLAG_LQ_RIVER: u32 = 0x04;
 const MCNK_FLAG_LQ_OCEAN: u32 = 0x08;
 const MCNK_FLAG_LQ_MAGMA: u32 = 0x10;
-const MCNK_FLAG_HAS_MCCV: u32 = 0x20;
-const MCNK_FLAG_UNK: u32 = 0x40;
-const MCNK_FLAG_DO_NOT_FIX_ALPHA_MAP: u32 = 0x200;
+const MCNK_FLAG_LQ_SLIME: u32 = 0x20;
+const MCNK_FLAG_HAS_MCCV: u32 = 0x40;
+const MCNK_FLAG_DO_NOT_FIX_ALPHA_MAP: u32 = 0x8000;
+const MCNK_FLAG_HIGH_RES_HOLES: u32 = 0x10000;
 
-#[derive(Clone, Debug)]
+/// Size of a MCSE sound emitter in 1.12, and from 2.x onwards.
+const MCSE_EMITTER_SIZE_VANILLA: u32 = 52;
+const MCSE_EMITTER_SIZE: u32 = 28;
+
+/// Size of the MCNK header, including its chunk header. Subchunk offsets are relative to the start of it.
+const MCNK_HEADER_SIZE: u32 = 8 + 128;
+
+#[derive(Clone, Copy, Debug, Default)]
 pub struct MCNKFlags {
     pub has_mcsh: bool,
     pub impass: bool,
     pub lq_river: bool,
     pub lq_ocean: bool,
     pub lq_magma: bool,
+    pub lq_slime: bool,
     pub has_mccv: bool,
 
     pub do_not_fix_alpha_map: bool,
+    /// The holes are stored as an 8x8 mask in place of the MCVT and MCNR offsets (from Mists of Pandaria).
+    pub high_res_holes: bool,
+
+    /// The undecoded bits from 0x80 to 0x4000 and above 0x10000, preserved when the MCNK is written.
+    pub unknown: u32,
+}
+
+impl MCNKFlags {
+    pub fn to_bits(&self) -> u32 {
+        shared::flags_to_bits(&[
+            (self.has_mcsh, MCNK_FLAG_HAS_MCSH),
+            (self.impass, MCNK_FLAG_IMPASS),
+            (self.lq_river, MCNK_FLAG_LQ_RIVER),
+            (self.lq_ocean, MCNK_FLAG_LQ_OCEAN),
+            (self.lq_magma, MCNK_FLAG_LQ_MAGMA),
+            (self.lq_slime, MCNK_FLAG_LQ_SLIME),
+            (self.has_mccv, MCNK_FLAG_HAS_MCCV),
+            (self.do_not_fix_alpha_map, MCNK_FLAG_DO_NOT_FIX_ALPHA_MAP),
+            (self.high_res_holes, MCNK_FLAG_HIGH_RES_HOLES),
+        ], self.unknown)
+    }
 }
 
 impl BinRead for MCNKFlags {
@@ -31,19 +64,25 @@ impl BinRead for MCNKFlags {
 
     fn read_options<R: std::io::Read + std::io::Seek>(
         reader: &mut R,
-        options: &binread::ReadOptions,
-        args: Self::Args,
+        _: &binread::ReadOptions,
+        _: Self::Args,
     ) -> binread::BinResult<Self> {
         let i: u32 = reader.read_le()?;
 
-        let has_mcsh = i & MCNK_FLAG_HAS_MCCV == MCNK_FLAG_HAS_MCCV;
+        let has_mcsh = i & MCNK_FLAG_HAS_MCSH == MCNK_FLAG_HAS_MCSH;
         let impass = i & MCNK_FLAG_IMPASS == MCNK_FLAG_IMPASS;
         let lq_river = i & MCNK_FLAG_LQ_RIVER == MCNK_FLAG_LQ_RIVER;
         let lq_ocean = i & MCNK_FLAG_LQ_OCEAN == MCNK_FLAG_LQ_OCEAN;
         let lq_magma = i & MCNK_FLAG_LQ_MAGMA == MCNK_FLAG_LQ_MAGMA;
+        let lq_slime = i & MCNK_FLAG_LQ_SLIME == MCNK_FLAG_LQ_SLIME;
         let has_mccv = i & MCNK_FLAG_HAS_MCCV == MCNK_FLAG_HAS_MCCV;
 
         let do_not_fix_alpha_map = i & MCNK_FLAG_DO_NOT_FIX_ALPHA_MAP == MCNK_FLAG_DO_NOT_FIX_ALPHA_MAP;
+        let high_res_holes = i & MCNK_FLAG_HIGH_RES_HOLES == MCNK_FLAG_HIGH_RES_HOLES;
+
+        let known = MCNK_FLAG_HAS_MCSH | MCNK_FLAG_IMPASS | MCNK_FLAG_LQ_RIVER | MCNK_FLAG_LQ_OCEAN
+            | MCNK_FLAG_LQ_MAGMA | MCNK_FLAG_LQ_SLIME | MCNK_FLAG_HAS_MCCV | MCNK_FLAG_DO_NOT_FIX_ALPHA_MAP
+            | MCNK_FLAG_HIGH_RES_HOLES;
 
         Ok(Self {
             has_mcsh,
@@ -51,8 +90,11 @@ impl BinRead for MCNKFlags {
             lq_river,
             lq_ocean,
             lq_magma,
+            lq_slime,
             has_mccv,
             do_not_fix_alpha_map,
+            high_res_holes,
+            unknown: i & !known,
         })
     }
 } 
@@ -80,6 +122,7 @@ pub struct MCNK {
     pub area_id: u32,
     pub n_map_obj_refs: u32,
     pub holes_low_res: u16,
+    unk: u16,
 
     pub low_res_texture_map: Vec<u16>,
 
@@ -93,21 +136,84 @@ pub struct MCNK {
 
     pub position: shared::C3Vector,
     pub ofs_mccv: u32,
+    unused: [u32; 2],
 
     // Subchunks:
-    pub mcvt: MCVT,
-    pub mcnr: MCNR,
+    /// Not present if the MCNK has the high-res holes flag set, as its offset holds part of the hole mask.
+    pub mcvt: Option<MCVT>,
+    /// Not present if the MCNK has the high-res holes flag set, as its offset holds part of the hole mask.
+    pub mcnr: Option<MCNR>,
     pub mcly: MCLY,
     pub mcrf: MCRF,
     pub mcal: MCAL,
+    /// Only present if the MCNK has the MCSH flag set.
+    pub mcsh: Option<MCSH>,
     pub mclq: MCLQ,
+    pub mcse: MCSE,
+    /// Only present from 3.x, if both the MCNK and the WDT's MPHD have the MCCV flag set.
+    pub mccv: Option<MCCV>,
+
+    // Subchunks that are written back as they were read (MCAL, MCSH, MCLQ, MCSE, and MCCV if it wasn't parsed).
+    raw_subchunks: Vec<RawSubchunk>,
+    // Bytes between the subchunks that aren't part of any of them, by the offset of the subchunk they
+    // come before (or `u32::MAX` for the bytes after the last one), so they can be written back in place.
+    gaps: Vec<(u32, Vec<u8>)>,
+}
+
+/// A subchunk kept as the bytes it was read from, so it can be written back unchanged.
+#[derive(Clone, Debug)]
+struct RawSubchunk {
+    token: String,
+    /// Offset of the subchunk header, relative to the start of the MCNK.
+    offset: u32,
+    /// The size stored in the subchunk header, which doesn't always match the data (e.g. MCLQ).
+    declared_size: u32,
+    data: Vec<u8>,
+}
+
+// Seeks to a subchunk and parses it, wrapping any error so the MCNK can be reported with the subchunk that failed.
+fn read_subchunk<R: Read + Seek, T>(reader: &mut R, offset: u32, token: &'static str, parse: impl FnOnce(&mut R) -> BinResult<T>) -> BinResult<T> {
+    reader.seek(SeekFrom::Start(offset.into()))
+        .map_err(binread::Error::from)
+        .and_then(|_| parse(reader))
+        .map_err(shared::SubchunkError::wrap(token, offset))
+}
+
+// Reads the subchunk at an offset from the MCNK header, if its token matches.
+fn read_raw_subchunk<R: Read + Seek>(reader: &mut R, offset: u32, token: &str, size: Option<u32>) -> BinResult<Option<RawSubchunk>> {
+    // The reader starts after the MCNK chunk header, so the subchunk header is 8 bytes before the offset.
+    if offset < 8 {
+        return Ok(None);
+    }
+    reader.seek(SeekFrom::Start((offset - 8).into()))?;
+
+    let found = match shared::token_parse(reader, &ReadOptions::default(), ()) {
+        Ok(found) => found,
+        Err(_) => return Ok(None),
+    };
+    if found != token {
+        return Ok(None);
+    }
+
+    let declared_size: u32 = reader.read_le()?;
+    let data = match shared::read_bytes(reader, size.unwrap_or(declared_size).into()) {
+        Ok(data) => data,
+        Err(_) => return Ok(None),
+    };
+
+    Ok(Some(RawSubchunk {
+        token: found,
+        offset,
+        declared_size,
+        data,
+    }))
 }
 
 // BinRead has to be manually implemented instead of derived for MCNK,
 // as the MCAL subchunk requires flags from the MCLY subchunk,
 // as well as flags from the WDT file.
 impl BinRead for MCNK {
-    type Args = (bool, );
+    type Args = (MPHDFlags, ClientVersion);
 
     fn read_options<R: Read + Seek>(
         reader: &mut R,
@@ -134,7 +240,7 @@ impl BinRead for MCNK {
         let area_id: u32 = reader.read_le()?;
         let n_map_obj_refs: u32 = reader.read_le()?;
         let holes_low_res: u16 = reader.read_le()?;
-        let _unk: u16 = reader.read_le()?;
+        let unk: u16 = reader.read_le()?;
 
         // We have to manually create a ReadOptions and mutate it, as it is non-exhaustative.
         let mut vec_options = ReadOptions::default();
@@ -151,38 +257,80 @@ impl BinRead for MCNK {
 
         let position: shared::C3Vector = reader.read_le()?;
         let ofs_mccv: u32 = reader.read_le()?;
-        let _unused: u32 = reader.read_le()?;
-        let _unused2: u32 = reader.read_le()?;
+        let unused: [u32; 2] = [reader.read_le()?, reader.read_le()?];
+
+        // With high-res holes, the MCVT and MCNR offsets hold the hole mask instead.
+        let (mcvt, mcnr): (Option<MCVT>, Option<MCNR>) = if flags.high_res_holes {
+            (None, None)
+        } else {
+            (
+                Some(read_subchunk(reader, ofs_height, "MCVT", |reader| reader.read_le_args((position, )))?),
+                Some(read_subchunk(reader, ofs_normal, "MCNR", |reader| reader.read_le())?),
+            )
+        };
+        let mcly: MCLY = read_subchunk(reader, ofs_layer, "MCLY", |reader| reader.read_le_args((n_layers,)))?;
+        let mcrf: MCRF = read_subchunk(reader, ofs_refs, "MCRF", |reader| reader.read_le_args((n_doodad_refs, n_map_obj_refs)))?;
 
-        reader.seek(SeekFrom::Start(ofs_height.into()))?;
-        let mcvt: MCVT = reader.read_le_args((position, ))?;
+        let mcal: MCAL = read_subchunk(reader, ofs_alpha, "MCAL", |reader| {
+            let mcal_subchunk = shared::read_bytes(reader, size_alpha.into())?;
 
-        reader.seek(SeekFrom::Start(ofs_normal.into()))?;
-        let mcnr: MCNR = reader.read_le()?;
+            let mut mcal_reader = std::io::Cursor::new(mcal_subchunk);
+            let mut mcal_layers: Vec<MCALLayer> = Vec::new();
+            for l in mcly.layers.iter() {
+                if l.flags.use_alpha {
+                    mcal_layers.push(mcal_reader.read_le_args::<MCALLayer>((args.0.uses_big_alpha(), l.flags.alpha_compressed, flags.do_not_fix_alpha_map))?);
+                }
+            }
 
-        reader.seek(SeekFrom::Start(ofs_layer.into()))?;
-        let mcly: MCLY = reader.read_le_args((n_layers,))?;
+            Ok(MCAL { layers: mcal_layers, big_alpha: args.0.uses_big_alpha() })
+        })?;
 
-        reader.seek(SeekFrom::Start(ofs_refs.into()))?;
-        let mcrf: MCRF = reader.read_le_args((n_doodad_refs, n_map_obj_refs))?;
+        let mcsh: Option<MCSH> = if flags.has_mcsh && ofs_shadow != 0 {
+            Some(read_subchunk(reader, ofs_shadow, "MCSH", |reader| reader.read_le_args((flags.do_not_fix_alpha_map,)))?)
+        } else {
+            None
+        };
 
-        reader.seek(SeekFrom::Start(ofs_alpha.into()))?;
-        let mut mcal_subchunk = vec![0; size_alpha.try_into().unwrap()];
-        reader.read_exact(&mut mcal_subchunk)?;
+        // From 3.x, liquids are normally stored in the ADT's MH2O chunk, with no MCLQ data at all.
+        let mclq: MCLQ = if args.1 >= ClientVersion::WotLK && size_liquid <= 8 {
+            MCLQ::default()
+        } else {
+            read_subchunk(reader, ofs_liquid, "MCLQ", |reader| reader.read_le_args((flags,)))?
+        };
 
-        let mut mcal_reader = std::io::Cursor::new(mcal_subchunk);
-        let mut mcal_layers: Vec<MCALLayer> = Vec::new();
-        for l in mcly.layers.iter() {
-            if l.flags.use_alpha {
-                mcal_layers.push(mcal_reader.read_le_args::<MCALLayer>((args.0, l.flags.alpha_compressed, flags.do_not_fix_alpha_map))?);
-            }
-        }
-        let mcal: MCAL = MCAL { layers: mcal_layers };
+        // Before 3.x, the MCCV offset is padding.
+        let mccv: Option<MCCV> = if args.1 >= ClientVersion::WotLK && flags.has_mccv && args.0.adt_has_mccv && ofs_mccv != 0 {
+            Some(read_subchunk(reader, ofs_mccv, "MCCV", |reader| reader.read_le())?)
+        } else {
+            None
+        };
 
-        reader.seek(SeekFrom::Start(ofs_liquid.into()))?;
-        let mclq: MCLQ = reader.read_le_args((flags.lq_river, flags.lq_ocean, flags.lq_magma))?;
+        let raw_subchunks: Vec<RawSubchunk> = [
+            read_raw_subchunk(reader, ofs_alpha, "MCAL", None)?,
+            read_raw_subchunk(reader, ofs_shadow, "MCSH", None)?,
+            // The MCLQ header doesn't hold its real size, which is only stored in the MCNK header.
+            read_raw_subchunk(reader, ofs_liquid, "MCLQ", Some(size_liquid.saturating_sub(8)))?,
+            read_raw_subchunk(reader, ofs_snd_emitters, "MCSE", None)?,
+            if mccv.is_none() { read_raw_subchunk(reader, ofs_mccv, "MCCV", None)? } else { None },
+        ].into_iter().flatten().collect();
+
+        // The emitter layout changed after 1.12, so it is taken from the subchunk size where possible.
+        let mcse_size = raw_subchunks.iter()
+            .find(|subchunk| subchunk.token == "MCSE")
+            .map(|subchunk| subchunk.declared_size);
+        let emitter_size = match mcse_size {
+            Some(size) if size == n_snd_emitters * MCSE_EMITTER_SIZE_VANILLA => MCSE_EMITTER_SIZE_VANILLA,
+            Some(size) if size == n_snd_emitters * MCSE_EMITTER_SIZE => MCSE_EMITTER_SIZE,
+            _ if args.1 == ClientVersion::Vanilla => MCSE_EMITTER_SIZE_VANILLA,
+            _ => MCSE_EMITTER_SIZE,
+        };
+        let mcse: MCSE = if n_snd_emitters > 0 && ofs_snd_emitters != 0 {
+            read_subchunk(reader, ofs_snd_emitters, "MCSE", |reader| reader.read_le_args((n_snd_emitters, emitter_size == MCSE_EMITTER_SIZE_VANILLA)))?
+        } else {
+            MCSE::default()
+        };
 
-        Ok(Self {
+        let mut mcnk = Self {
             flags,
 
             x,
@@ -203,6 +351,7 @@ impl BinRead for MCNK {
             area_id,
             n_map_obj_refs,
             holes_low_res,
+            unk,
 
             low_res_texture_map,
             doodad_stencil,
@@ -215,54 +364,413 @@ impl BinRead for MCNK {
 
             position,
             ofs_mccv,
+            unused,
 
             mcvt,
             mcnr,
             mcly,
             mcrf,
             mcal,
+            mcsh,
             mclq,
-        })
+            mcse,
+            mccv,
+
+            raw_subchunks,
+            gaps: Vec::new(),
+        };
+
+        // Keep anything the subchunks don't cover, so files with padding between them round-trip exactly.
+        let end = reader.seek(SeekFrom::End(0))? as u32 + 8;
+        let mut position = MCNK_HEADER_SIZE;
+        for subchunk in mcnk.subchunks()? {
+            if subchunk.offset > position {
+                reader.seek(SeekFrom::Start((position - 8).into()))?;
+                mcnk.gaps.push((subchunk.offset, shared::read_bytes(reader, (subchunk.offset - position).into())?));
+            }
+            position = position.max(subchunk.offset.saturating_add(8 + subchunk.data.len() as u32));
+        }
+        if end > position {
+            reader.seek(SeekFrom::Start((position - 8).into()))?;
+            mcnk.gaps.push((u32::MAX, shared::read_bytes(reader, (end - position).into())?));
+        }
+
+        Ok(mcnk)
+    }
+}
+
+impl MCNK {
+    /// Combines the alpha maps and shadow map into a single 64x64 RGBA texture, row by row.
+    ///
+    /// Like the client's terrain alpha texture, the alpha maps of the (up to) three blended layers
+    /// are stored in the red, green and blue channels, scaled to 0-255. The alpha channel is 255
+    /// where the MCSH shadow map is set, and 0 everywhere else (or if there is no shadow map).
+    pub fn alpha_texture(&self) -> Vec<[u8; 4]> {
+        let scale = if self.mcal.big_alpha { 1 } else { 17 };
+
+        (0..64 * 64).map(|i| {
+            let mut pixel = [0u8; 4];
+            for (channel, layer) in self.mcal.layers.iter().take(3).enumerate() {
+                pixel[channel] = layer.alpha_map.get(i).map_or(0, |alpha| alpha.saturating_mul(scale));
+            }
+            if self.mcsh.as_ref().is_some_and(|mcsh| mcsh.shadow_map[i]) {
+                pixel[3] = 255;
+            }
+
+            pixel
+        }).collect()
+    }
+
+    /// The 8x8 hole mask, if the MCNK has the high-res holes flag set.
+    pub fn holes_high_res(&self) -> Option<u64> {
+        self.flags.high_res_holes.then(|| u64::from(self.ofs_height) | u64::from(self.ofs_normal) << 32)
+    }
+
+    /// Which of the 8x8 quads are holes, row by row, from either the high-res or low-res mask.
+    pub fn holes(&self) -> [[bool; 8]; 8] {
+        match self.holes_high_res() {
+            Some(mask) => high_res_holes(mask),
+            None => low_res_holes(self.holes_low_res),
+        }
+    }
+
+    /// Position within the 8x8 quads as (row, column), or `None` if the world position is outside of the MCNK.
+    fn quad_position(&self, world_x: f32, world_y: f32) -> Option<(f32, f32)> {
+        // Rows run south from the MCNK's position along the world x axis, columns along the world y axis.
+        let row = (self.position.x - world_x) / QUAD_SIZE;
+        let column = (self.position.y - world_y) / QUAD_SIZE;
+
+        // Allow for rounding, so positions on the edge between two MCNKs are in both.
+        let range = -1e-3..=8.0 + 1e-3;
+        (range.contains(&row) && range.contains(&column)).then_some((row.clamp(0.0, 8.0), column.clamp(0.0, 8.0)))
+    }
+
+    /// Whether a world position is within the MCNK, including its edges.
+    pub fn contains(&self, world_x: f32, world_y: f32) -> bool {
+        self.quad_position(world_x, world_y).is_some()
+    }
+
+    /// Whether a world position within the MCNK is in a hole. Positions outside of the MCNK are never holes.
+    pub fn is_hole(&self, world_x: f32, world_y: f32) -> bool {
+        match self.quad_position(world_x, world_y) {
+            Some((row, column)) => self.holes()[(row as usize).min(7)][(column as usize).min(7)],
+            None => false,
+        }
+    }
+
+    /// The terrain height at a world position, or `None` if it is outside of the MCNK, in a hole,
+    /// or the MCNK has no MCVT.
+    ///
+    /// Like the client, each quad is split into four triangles around its inner vertex, and the
+    /// height is interpolated on the plane of the triangle the position falls in.
+    pub fn height_at(&self, world_x: f32, world_y: f32) -> Option<f32> {
+        let (row, column) = self.quad_position(world_x, world_y)?;
+        let (r, c) = ((row as usize).min(7), (column as usize).min(7));
+        if self.holes()[r][c] {
+            return None;
+        }
+
+        // Position within the quad, from its north-west corner.
+        let (dx, dy) = (column - c as f32, row - r as f32);
+
+        let heights = &self.mcvt.as_ref()?.heights;
+        let corner = |x: usize, y: usize| (x as f32, y as f32, heights.outer[r + y][c + x].world.z);
+        let centre = (0.5, 0.5, heights.inner[r][c].world.z);
+
+        // The diagonals of the quad split it into a north, south, west and east triangle.
+        let (a, b) = if dy <= dx && dy <= 1.0 - dx {
+            (corner(0, 0), corner(1, 0))
+        } else if dy >= dx && dy >= 1.0 - dx {
+            (corner(0, 1), corner(1, 1))
+        } else if dx < dy {
+            (corner(0, 0), corner(0, 1))
+        } else {
+            (corner(1, 0), corner(1, 1))
+        };
+
+        Some(interpolate_triangle((dx, dy), a, b, centre))
     }
 }
 
-static ADT_SIZE: f32 = 533.0 + (1.0 / 3.0);
-static QUAD_SIZE: f32 = ADT_SIZE / 128.0;
+// Interpolates the height at a point on the plane of a triangle, using barycentric coordinates.
+fn interpolate_triangle(point: (f32, f32), a: (f32, f32, f32), b: (f32, f32, f32), c: (f32, f32, f32)) -> f32 {
+    let det = (b.1 - c.1) * (a.0 - c.0) + (c.0 - b.0) * (a.1 - c.1);
+    let wa = ((b.1 - c.1) * (point.0 - c.0) + (c.0 - b.0) * (point.1 - c.1)) / det;
+    let wb = ((c.1 - a.1) * (point.0 - c.0) + (a.0 - c.0) * (point.1 - c.1)) / det;
 
-pub fn parse_heightmap(raw: Vec<f32>, offset:shared::C3Vector) -> Vec<shared::C3Vector> {
-    let mut parsed: Vec<shared::C3Vector> = Vec::new();
-    for (i, height) in raw.iter().enumerate() {
-        // From https://github.com/namreeb/namigator/blob/master/parser/Adt/Chunks/MCNK.cpp#L117.
-        // TODO: This currently uses the distant view heightmap, which causes some irregularities.
+    wa * a.2 + wb * b.2 + (1.0 - wa - wb) * c.2
+}
 
-        // If i % 17 > 8, this is the inner part of a quad.
-        let inner = (i % 17) > 8;
+/// Expands a 4x4 low-res hole mask, where each bit covers 2x2 quads, into the 8x8 quads row by row.
+pub fn low_res_holes(mask: u16) -> [[bool; 8]; 8] {
+    let mut holes = [[false; 8]; 8];
+    for (y, row) in holes.iter_mut().enumerate() {
+        for (x, hole) in row.iter_mut().enumerate() {
+            *hole = mask & (1 << ((y / 2) * 4 + x / 2)) != 0;
+        }
+    }
 
-        let i: i32 = i.try_into().unwrap();
+    holes
+}
+
+/// Expands an 8x8 high-res hole mask, with a byte per row, into the 8x8 quads row by row.
+pub fn high_res_holes(mask: u64) -> [[bool; 8]; 8] {
+    let mut holes = [[false; 8]; 8];
+    for (y, row) in holes.iter_mut().enumerate() {
+        for (x, hole) in row.iter_mut().enumerate() {
+            *hole = mask & (1 << (y * 8 + x)) != 0;
+        }
+    }
 
-        let x: i32 = if inner {(i - 9) % 17} else {i % 17};
-        let y: i32 = i / 17;
+    holes
+}
 
-        let inner_offset: f32 = if inner {QUAD_SIZE / 2.0} else {0.0};
+impl MCNK {
+    // The subchunks to write, in the order they were read in, with their offsets from when they were read.
+    fn subchunks(&self) -> std::io::Result<Vec<RawSubchunk>> {
+        let mut subchunks = Vec::new();
+        if let Some(mcvt) = &self.mcvt {
+            let data = mcvt.to_bytes()?;
+            subchunks.push(RawSubchunk { token: "MCVT".to_string(), offset: self.ofs_height, declared_size: data.len() as u32, data });
+        }
+        if let Some(mcnr) = &self.mcnr {
+            // The 13 bytes of padding at the end of MCNR aren't counted in its size.
+            let data = mcnr.to_bytes()?;
+            let declared_size = (data.len() - mcnr.padding.len()) as u32;
+            subchunks.push(RawSubchunk { token: "MCNR".to_string(), offset: self.ofs_normal, declared_size, data });
+        }
+        for (token, offset, data) in [("MCLY", self.ofs_layer, self.mcly.to_bytes()?), ("MCRF", self.ofs_refs, self.mcrf.to_bytes()?)] {
+            subchunks.push(RawSubchunk { token: token.to_string(), offset, declared_size: data.len() as u32, data });
+        }
 
-        let z: f32 = offset.z + height;
+        if let Some(mccv) = &self.mccv {
+            let data = mccv.to_bytes()?;
+            subchunks.push(RawSubchunk { token: "MCCV".to_string(), offset: self.ofs_mccv, declared_size: data.len() as u32, data });
+        }
 
-        let world_x = offset.x - ((y as f32) * QUAD_SIZE) - inner_offset;
-        let world_y = offset.y - ((x as f32) * QUAD_SIZE) - inner_offset;
+        // Lay the subchunks out in the order they were read in, so unmodified chunks round-trip exactly.
+        subchunks.extend(self.raw_subchunks.iter().cloned());
+        subchunks.sort_by_key(|subchunk| subchunk.offset);
 
-        parsed.push(shared::C3Vector { x: world_x, y: world_y, z });
+        Ok(subchunks)
     }
 
-    parsed
+    fn gap(&self, offset: u32) -> &[u8] {
+        self.gaps.iter().find(|(gap_offset, _)| *gap_offset == offset).map_or(&[], |(_, gap)| gap)
+    }
+}
+
+impl ChunkWrite for MCNK {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let subchunks = self.subchunks()?;
+
+        let (mut ofs_height, mut ofs_normal, mut ofs_layer, mut ofs_refs) = (self.ofs_height, self.ofs_normal, self.ofs_layer, self.ofs_refs);
+        let (mut ofs_alpha, mut ofs_shadow, mut ofs_snd_emitters) = (self.ofs_alpha, self.ofs_shadow, self.ofs_snd_emitters);
+        let (mut ofs_liquid, mut ofs_mccv) = (self.ofs_liquid, self.ofs_mccv);
+
+        let mut body: Vec<u8> = Vec::new();
+        for subchunk in subchunks.iter() {
+            body.write_all(self.gap(subchunk.offset))?;
+
+            let offset = MCNK_HEADER_SIZE + body.len() as u32;
+            match subchunk.token.as_str() {
+                "MCVT" => ofs_height = offset,
+                "MCNR" => ofs_normal = offset,
+                "MCLY" => ofs_layer = offset,
+                "MCRF" => ofs_refs = offset,
+                "MCAL" => ofs_alpha = offset,
+                "MCSH" => ofs_shadow = offset,
+                "MCLQ" => ofs_liquid = offset,
+                "MCSE" => ofs_snd_emitters = offset,
+                "MCCV" => ofs_mccv = offset,
+                _ => {},
+            }
+
+            shared::write_chunk_header(&mut body, &subchunk.token, subchunk.declared_size)?;
+            body.write_all(&subchunk.data)?;
+        }
+        body.write_all(self.gap(u32::MAX))?;
+
+        let header = [
+            self.flags.to_bits(),
+            self.x,
+            self.y,
+            self.mcly.layers.len() as u32,
+            self.mcrf.doodad_refs.len() as u32,
+            ofs_height,
+            ofs_normal,
+            ofs_layer,
+            ofs_refs,
+            ofs_alpha,
+            self.size_alpha,
+            ofs_shadow,
+            self.size_shadow,
+            self.area_id,
+            self.mcrf.n_map_obj_refs.len() as u32,
+        ];
+        for value in header {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+
+        writer.write_all(&self.holes_low_res.to_le_bytes())?;
+        writer.write_all(&self.unk.to_le_bytes())?;
+        for value in &self.low_res_texture_map {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.write_all(&self.doodad_stencil)?;
+
+        for value in [ofs_snd_emitters, self.n_snd_emitters, ofs_liquid, self.size_liquid] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        self.position.write_data(writer)?;
+        for value in [ofs_mccv, self.unused[0], self.unused[1]] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+
+        writer.write_all(&body)
+    }
+}
+
+static QUAD_SIZE: f32 = shared::ADT_SIZE / 128.0;
+
+/// A vertex of the MCVT heightmap.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChunkVertex {
+    /// The position within the MCNK: the distance east (`x`) and south (`y`) from its
+    /// north-west corner, and the height relative to the MCNK position (`z`), as stored in MCVT.
+    pub local: shared::C3Vector,
+    pub world: shared::C3Vector,
+}
+
+/// The MCVT heightmap, split into its two grids.
+///
+/// MCVT interleaves rows of 9 outer vertices, on the corners of the 8x8 quads, with rows of 8
+/// inner vertices in the centres of the quads, for 145 heights in total.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChunkHeights {
+    /// The 9x9 vertices on the corners of the quads, row by row from the north-west corner.
+    pub outer: [[ChunkVertex; 9]; 9],
+    /// The 8x8 vertices in the centres of the quads, row by row from the north-west corner.
+    pub inner: [[ChunkVertex; 8]; 8],
+}
+
+impl ChunkHeights {
+    /// Splits the 145 raw MCVT heights into the outer and inner grids, for an MCNK at a world position.
+    pub fn new(raw_heights: &[f32], position: shared::C3Vector) -> Self {
+        // The world x axis runs north and the world y axis runs west, so both decrease away from the corner.
+        let vertex = |row: f32, column: f32, height: f32| ChunkVertex {
+            local: shared::C3Vector { x: column * QUAD_SIZE, y: row * QUAD_SIZE, z: height },
+            world: shared::C3Vector {
+                x: position.x - row * QUAD_SIZE,
+                y: position.y - column * QUAD_SIZE,
+                z: position.z + height,
+            },
+        };
+        let height = |i: usize| raw_heights.get(i).copied().unwrap_or(0.0);
+
+        let mut outer = [[vertex(0.0, 0.0, 0.0); 9]; 9];
+        for (row, vertices) in outer.iter_mut().enumerate() {
+            for (column, v) in vertices.iter_mut().enumerate() {
+                *v = vertex(row as f32, column as f32, height(row * 17 + column));
+            }
+        }
+
+        let mut inner = [[vertex(0.0, 0.0, 0.0); 8]; 8];
+        for (row, vertices) in inner.iter_mut().enumerate() {
+            for (column, v) in vertices.iter_mut().enumerate() {
+                *v = vertex(row as f32 + 0.5, column as f32 + 0.5, height(row * 17 + 9 + column));
+            }
+        }
+
+        Self {
+            outer,
+            inner,
+        }
+    }
+
+    /// Computes the normals of the vertices from their heights, as the average of the normals of
+    /// the triangles around them, weighted by area.
+    ///
+    /// Only the triangles of this MCNK are used, so normals on its edges can differ from the
+    /// stored ones, which also take the neighbouring MCNKs into account.
+    pub fn normals(&self) -> ChunkNormals {
+        let zero = shared::C3Vector { x: 0.0, y: 0.0, z: 0.0 };
+        let mut outer = [[zero; 9]; 9];
+        let mut inner = [[zero; 8]; 8];
+
+        let add = |sum: &mut shared::C3Vector, normal: shared::C3Vector| {
+            *sum = shared::C3Vector { x: sum.x + normal.x, y: sum.y + normal.y, z: sum.z + normal.z };
+        };
+        let sub = |a: shared::C3Vector, b: shared::C3Vector| shared::C3Vector { x: a.x - b.x, y: a.y - b.y, z: a.z - b.z };
+
+        for (row, centres) in self.inner.iter().enumerate() {
+            for (column, centre) in centres.iter().map(|vertex| vertex.world).enumerate() {
+                let corners = [(row, column), (row, column + 1), (row + 1, column + 1), (row + 1, column)];
+
+                // The four triangles between each edge of the quad and its centre.
+                for (i, &(ar, ac)) in corners.iter().enumerate() {
+                    let (br, bc) = corners[(i + 1) % 4];
+                    let (a, b) = (self.outer[ar][ac].world, self.outer[br][bc].world);
+
+                    let mut normal = sub(b, a).cross(&sub(centre, a));
+                    if normal.z < 0.0 {
+                        normal = shared::C3Vector { x: -normal.x, y: -normal.y, z: -normal.z };
+                    }
+
+                    add(&mut outer[ar][ac], normal);
+                    add(&mut outer[br][bc], normal);
+                    add(&mut inner[row][column], normal);
+                }
+            }
+        }
+
+        ChunkNormals {
+            outer: outer.map(|row| row.map(|normal| normal.normalized())),
+            inner: inner.map(|row| row.map(|normal| normal.normalized())),
+        }
+    }
+}
+
+/// Unit normals of the MCVT vertices, in the same grids and world axes as [`ChunkHeights`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChunkNormals {
+    pub outer: [[shared::C3Vector; 9]; 9],
+    pub inner: [[shared::C3Vector; 8]; 8],
+}
+
+impl ChunkNormals {
+    /// The normals in the interleaved order of MCVT and MCNR.
+    pub fn to_interleaved(&self) -> Vec<shared::C3Vector> {
+        (0..9).flat_map(|row| {
+            let inner = self.inner.get(row).map_or(&[][..], |inner| &inner[..]);
+            self.outer[row].iter().chain(inner).copied()
+        }).collect()
+    }
 }
 
 #[derive(Clone, Debug, BinRead)]
 #[br(little, import(offset:shared::C3Vector))]
 pub struct MCVT {
-    #[br(count = 145, map = |raw: Vec<f32>| parse_heightmap(raw, offset))]
-    pub heights: Vec<shared::C3Vector>,
+    /// Heights as stored in the file, relative to the MCNK position.
+    #[br(count = 145)]
+    pub raw_heights: Vec<f32>,
+    #[br(calc = ChunkHeights::new(&raw_heights, offset))]
+    pub heights: ChunkHeights,
 }
 
+impl ChunkWrite for MCVT {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        for height in &self.raw_heights {
+            writer.write_all(&height.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A normal as stored in MCNR, with each component scaled to -127..=127.
+///
+/// The components are in the same world axes as the MCVT vertices, so only need scaling.
 #[derive(Clone, Debug, BinRead)]
 #[br(little)]
 pub struct MCNREntry {
@@ -271,11 +779,175 @@ pub struct MCNREntry {
     pub z: i8,
 }
 
+impl MCNREntry {
+    /// The normal as a unit vector.
+    pub fn normal(&self) -> shared::C3Vector {
+        shared::C3Vector { x: f32::from(self.x) / 127.0, y: f32::from(self.y) / 127.0, z: f32::from(self.z) / 127.0 }.normalized()
+    }
+
+    pub fn from_normal(normal: shared::C3Vector) -> Self {
+        let normal = normal.normalized();
+        let scale = |value: f32| (value * 127.0).round().clamp(-127.0, 127.0) as i8;
+
+        Self { x: scale(normal.x), y: scale(normal.y), z: scale(normal.z) }
+    }
+}
+
 #[derive(Clone, Debug, BinRead)]
 #[br(little)]
 pub struct MCNR {
+    /// One normal per MCVT vertex, in the same interleaved order.
     #[br(count = 145)]
     pub normals: Vec<MCNREntry>,
+    /// Follows the normals, but isn't counted in the size of the subchunk. Kept so it can be written back.
+    #[br(count = 13)]
+    pub padding: Vec<u8>,
+}
+
+impl MCNR {
+    /// The normals as unit vectors, split into the outer and inner grids like [`ChunkHeights`].
+    pub fn normals(&self) -> ChunkNormals {
+        let up = shared::C3Vector { x: 0.0, y: 0.0, z: 1.0 };
+        let normal = |i: usize| self.normals.get(i).map_or(up, MCNREntry::normal);
+
+        let mut outer = [[up; 9]; 9];
+        for (row, normals) in outer.iter_mut().enumerate() {
+            for (column, n) in normals.iter_mut().enumerate() {
+                *n = normal(row * 17 + column);
+            }
+        }
+
+        let mut inner = [[up; 8]; 8];
+        for (row, normals) in inner.iter_mut().enumerate() {
+            for (column, n) in normals.iter_mut().enumerate() {
+                *n = normal(row * 17 + 9 + column);
+            }
+        }
+
+        ChunkNormals {
+            outer,
+            inner,
+        }
+    }
+
+    /// Replaces the stored normals, e.g. with ones recomputed by [`ChunkHeights::normals`].
+    pub fn set_normals(&mut self, normals: &ChunkNormals) {
+        self.normals = normals.to_interleaved().into_iter().map(MCNREntry::from_normal).collect();
+    }
+}
+
+impl ChunkWrite for MCNR {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        for normal in &self.normals {
+            writer.write_all(&[normal.x as u8, normal.y as u8, normal.z as u8])?;
+        }
+
+        writer.write_all(&self.padding)
+    }
+}
+
+/// The size of a sound emitter, which changed after 1.12.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MCSEEmitterSize {
+    /// 1.12 emitters are spheres, fading out between the distances.
+    Distances {
+        min_distance: f32,
+        max_distance: f32,
+        cutoff_distance: f32,
+    },
+    /// From 2.x, emitters are boxes with the given size around their position.
+    Box(shared::C3Vector),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MCSEEmitter {
+    /*
+    1.12:
+    uint32_t soundPointID;
+    uint32_t soundNameID;          // SoundEntries
+    C3Vector pos;
+    float minDistance;
+    float maxDistance;
+    float cutoffDistance;
+    uint16_t startTime, endTime, mode, groupSilenceMin, groupSilenceMax, playInstancesMin, playInstancesMax;
+    uint8_t loopCountMin, loopCountMax;
+    uint16_t interSoundGapMin, interSoundGapMax;
+
+    2.x onwards:
+    uint32_t entry_id;             // SoundEntriesAdvanced
+    C3Vector position;
+    C3Vector size;
+    */
+    /// The SoundEntries id in 1.12, or the SoundEntriesAdvanced id from 2.x.
+    pub sound_entry_id: u32,
+    /// Only stored in 1.12.
+    pub sound_point_id: Option<u32>,
+    /// Position of the emitter, as stored in the file.
+    pub position: shared::C3Vector,
+    pub size: MCSEEmitterSize,
+}
+
+impl BinRead for MCSEEmitter {
+    type Args = (bool,);
+
+    fn read_options<R: Read + Seek>(
+        reader: &mut R,
+        _: &ReadOptions,
+        args: Self::Args,
+    ) -> BinResult<Self> {
+        let (vanilla,) = args;
+
+        if vanilla {
+            let sound_point_id: u32 = reader.read_le()?;
+            let sound_entry_id: u32 = reader.read_le()?;
+            let position: shared::C3Vector = reader.read_le()?;
+            let size = MCSEEmitterSize::Distances {
+                min_distance: reader.read_le()?,
+                max_distance: reader.read_le()?,
+                cutoff_distance: reader.read_le()?,
+            };
+
+            // The timing and repetition fields aren't decoded.
+            reader.seek(SeekFrom::Current(i64::from(MCSE_EMITTER_SIZE_VANILLA) - 32))?;
+
+            Ok(Self { sound_entry_id, sound_point_id: Some(sound_point_id), position, size })
+        } else {
+            Ok(Self {
+                sound_entry_id: reader.read_le()?,
+                sound_point_id: None,
+                position: reader.read_le()?,
+                size: MCSEEmitterSize::Box(reader.read_le()?),
+            })
+        }
+    }
+}
+
+/// Ambient sound emitters in the MCNK.
+#[derive(Clone, Debug, Default, BinRead)]
+#[br(little, import(count: u32, vanilla: bool))]
+pub struct MCSE {
+    #[br(count = count, args(vanilla))]
+    pub emitters: Vec<MCSEEmitter>,
+}
+
+/// Vertex colours, multiplied with the terrain textures.
+#[derive(Clone, Debug, BinRead)]
+#[br(little)]
+pub struct MCCV {
+    /// One colour per vertex, in the same order as the MCVT heights. 0x7F is neutral,
+    /// so values above it brighten the texture.
+    #[br(count = 145)]
+    pub colors: Vec<shared::CImVector>,
+}
+
+impl ChunkWrite for MCCV {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        for color in &self.colors {
+            color.write_data(writer)?;
+        }
+
+        Ok(())
+    }
 }
 
 const MCLY_FLAG_ANIMATE_45: u32 = 0x01;
@@ -290,12 +962,71 @@ const MCLY_FLAG_USE_ALPHA: u32 = 0x100;
 const MCLY_FLAG_ALPHA_COMPRESSED: u32 = 0x200;
 const MCLY_FLAG_REFLECTION: u32 = 0x400;
 
-#[derive(Clone, Debug)]
+/// The scrolling animation of a texture layer, e.g. for water and lava.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureAnimation {
+    /// Direction the texture scrolls in, in 45° steps.
+    pub direction_degrees: f32,
+    /// Scrolling speed from 0 to 7, combined from the fast, faster and fastest bits.
+    pub speed: u8,
+}
+
+#[derive(Clone, Debug, Default)]
 pub struct MCLYFlags {
+    // The three rotation bits and the three speed bits are each a 3-bit number.
     pub animate_45: bool,
     pub animate_90: bool,
+    pub animate_180: bool,
+    pub anim_fast: bool,
+    pub anim_faster: bool,
+    pub anim_fastest: bool,
+    pub animate: bool,
+    /// Render the layer overbright, e.g. for lava.
+    pub glow: bool,
     pub use_alpha: bool,
     pub alpha_compressed: bool,
+    /// Use the skybox reflection (cubemap) on the layer.
+    pub reflection: bool,
+
+    /// Bits from 0x800 up, used by later expansions, kept so the layer is written back unchanged.
+    pub unknown: u32,
+}
+
+impl MCLYFlags {
+    /// The animation of the layer, if it is animated.
+    pub fn animation(&self) -> Option<TextureAnimation> {
+        if !self.animate {
+            return None;
+        }
+
+        let direction = [self.animate_45, self.animate_90, self.animate_180].iter()
+            .zip([1u8, 2, 4])
+            .fold(0, |steps, (set, step)| if *set { steps + step } else { steps });
+        let speed = [self.anim_fast, self.anim_faster, self.anim_fastest].iter()
+            .zip([1u8, 2, 4])
+            .fold(0, |speed, (set, step)| if *set { speed + step } else { speed });
+
+        Some(TextureAnimation {
+            direction_degrees: f32::from(direction) * 45.0,
+            speed,
+        })
+    }
+
+    pub fn to_bits(&self) -> u32 {
+        shared::flags_to_bits(&[
+            (self.animate_45, MCLY_FLAG_ANIMATE_45),
+            (self.animate_90, MCLY_FLAG_ANIMATE_90),
+            (self.animate_180, MCLY_FLAG_ANIMATE_180),
+            (self.anim_fast, MCLY_FLAG_ANIM_FAST),
+            (self.anim_faster, MCLY_FLAG_ANIM_FASTER),
+            (self.anim_fastest, MCLY_FLAG_ANIM_FASTEST),
+            (self.animate, MCLY_FLAG_ANIMATE),
+            (self.glow, MCLY_FLAG_GLOW),
+            (self.use_alpha, MCLY_FLAG_USE_ALPHA),
+            (self.alpha_compressed, MCLY_FLAG_ALPHA_COMPRESSED),
+            (self.reflection, MCLY_FLAG_REFLECTION),
+        ], self.unknown)
+    }
 }
 
 impl BinRead for MCLYFlags {
@@ -303,22 +1034,31 @@ impl BinRead for MCLYFlags {
 
     fn read_options<R: std::io::Read + std::io::Seek>(
         reader: &mut R,
-        options: &binread::ReadOptions,
-        args: Self::Args,
+        _: &binread::ReadOptions,
+        _: Self::Args,
     ) -> binread::BinResult<Self> {
         let i: u32 = reader.read_le()?;
 
-        let use_alpha = i & MCLY_FLAG_USE_ALPHA == MCLY_FLAG_USE_ALPHA;
-        let alpha_compressed = i & MCLY_FLAG_ALPHA_COMPRESSED == MCLY_FLAG_ALPHA_COMPRESSED;
+        let known = MCLY_FLAG_ANIMATE_45 | MCLY_FLAG_ANIMATE_90 | MCLY_FLAG_ANIMATE_180 | MCLY_FLAG_ANIM_FAST
+            | MCLY_FLAG_ANIM_FASTER | MCLY_FLAG_ANIM_FASTEST | MCLY_FLAG_ANIMATE | MCLY_FLAG_GLOW
+            | MCLY_FLAG_USE_ALPHA | MCLY_FLAG_ALPHA_COMPRESSED | MCLY_FLAG_REFLECTION;
 
         Ok(Self {
-            animate_45: false,
-            animate_90: false,
-            use_alpha,
-            alpha_compressed,
+            animate_45: i & MCLY_FLAG_ANIMATE_45 == MCLY_FLAG_ANIMATE_45,
+            animate_90: i & MCLY_FLAG_ANIMATE_90 == MCLY_FLAG_ANIMATE_90,
+            animate_180: i & MCLY_FLAG_ANIMATE_180 == MCLY_FLAG_ANIMATE_180,
+            anim_fast: i & MCLY_FLAG_ANIM_FAST == MCLY_FLAG_ANIM_FAST,
+            anim_faster: i & MCLY_FLAG_ANIM_FASTER == MCLY_FLAG_ANIM_FASTER,
+            anim_fastest: i & MCLY_FLAG_ANIM_FASTEST == MCLY_FLAG_ANIM_FASTEST,
+            animate: i & MCLY_FLAG_ANIMATE == MCLY_FLAG_ANIMATE,
+            glow: i & MCLY_FLAG_GLOW == MCLY_FLAG_GLOW,
+            use_alpha: i & MCLY_FLAG_USE_ALPHA == MCLY_FLAG_USE_ALPHA,
+            alpha_compressed: i & MCLY_FLAG_ALPHA_COMPRESSED == MCLY_FLAG_ALPHA_COMPRESSED,
+            reflection: i & MCLY_FLAG_REFLECTION == MCLY_FLAG_REFLECTION,
+            unknown: i & !known,
         })
     }
-} 
+}
 
 #[derive(Clone, Debug, BinRead)]
 #[br(little)]
@@ -336,6 +1076,18 @@ pub struct MCLY {
     pub layers: Vec<MCLYLayer>,
 }
 
+impl ChunkWrite for MCLY {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        for layer in &self.layers {
+            for value in [layer.texture_id, layer.flags.to_bits(), layer.offset_in_mcal, layer.effect_id] {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug, BinRead)]
 #[br(little, import(n_doodad_refs: u32, n_map_obj_refs: u32))]
 pub struct MCRF {
@@ -345,6 +1097,28 @@ pub struct MCRF {
     n_map_obj_refs: Vec<u32>,
 }
 
+impl MCRF {
+    /// Indices into the ADT's MDDF entries of the doodads that overlap the MCNK.
+    pub fn doodad_refs(&self) -> &[u32] {
+        &self.doodad_refs
+    }
+
+    /// Indices into the ADT's MODF entries of the WMOs that overlap the MCNK.
+    pub fn map_obj_refs(&self) -> &[u32] {
+        &self.n_map_obj_refs
+    }
+}
+
+impl ChunkWrite for MCRF {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        for value in self.doodad_refs.iter().chain(self.n_map_obj_refs.iter()) {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct MCALLayer {
     pub alpha_map: Vec<u8>,
@@ -355,7 +1129,7 @@ impl BinRead for MCALLayer {
 
     fn read_options<R: Read + Seek>(
         reader: &mut R,
-        options: &ReadOptions,
+        _: &ReadOptions,
         args: Self::Args,
     ) -> BinResult<Self> {
         let (full_size, compressed, do_not_fix_alpha_map) = args;
@@ -364,27 +1138,23 @@ impl BinRead for MCALLayer {
             true => {
                 let mut data: Vec<u8> = Vec::new();
                 // TODO: Ignore any bytes over 4096.
-                loop {
-                    if let Ok(count_and_mode) = reader.read_be::<u8>() {
-                        let fill = count_and_mode & 0x80 == 0x80;
-                        let count = count_and_mode & 0x7F;
-
-                        // fill
-                        if fill {
-                            let value = reader.read_le::<u8>()?;
-                            for _ in 0..count {
-                                data.push(value);
-                            }
+                while let Ok(count_and_mode) = reader.read_be::<u8>() {
+                    let fill = count_and_mode & 0x80 == 0x80;
+                    let count = count_and_mode & 0x7F;
+
+                    // fill
+                    if fill {
+                        let value = reader.read_le::<u8>()?;
+                        for _ in 0..count {
+                            data.push(value);
                         }
-                        // copy
-                        else {
-                            for _ in 0..count {
-                                let value = reader.read_le::<u8>()?;
-                                data.push(value);
-                            }
+                    }
+                    // copy
+                    else {
+                        for _ in 0..count {
+                            let value = reader.read_le::<u8>()?;
+                            data.push(value);
                         }
-                    } else {
-                        break;
                     }
                 }
 
@@ -408,7 +1178,7 @@ impl BinRead for MCALLayer {
             }
         }
         else {
-            // Chunks marked as half-size (MPHD flags *not* set) need to have each
+            // Chunks marked as half-size (MPHD big alpha flags *not* set) need to have each
             // u8 value splt in half, then converted back into u8s in order to construct
             // the full 64x64 alpha map.
             while alpha_map.len() < 4096 {
@@ -422,17 +1192,7 @@ impl BinRead for MCALLayer {
         }
 
         if !do_not_fix_alpha_map {
-            for i in 0..alpha_map.len() {
-                // Replace the last row with the previous rows value.
-                if i > (4096 - 64) {
-                    alpha_map[i] = alpha_map[i-64];
-                }
-
-                // Replace the last column with the previous columns value.
-                if i > 0 && (i+1).rem_euclid(64) == 0 {
-                    alpha_map[i] = alpha_map[i-1];
-                }
-            }
+            fix_map_edges(&mut alpha_map);
         }
 
         Ok(Self {
@@ -441,9 +1201,128 @@ impl BinRead for MCALLayer {
     }
 }
 
+// Replaces the last row and column of a 64x64 map with the ones before them, as the client does
+// for alpha and shadow maps unless the MCNK has the do_not_fix_alpha_map flag.
+//
+// The first point of the last row is left as it is, as it always has been for alpha maps.
+fn fix_map_edges<T: Copy>(map: &mut [T]) {
+    for i in 0..map.len() {
+        // Replace the last row with the previous rows value.
+        if i > (4096 - 64) {
+            map[i] = map[i - 64];
+        }
+
+        // Replace the last column with the previous columns value.
+        if i > 0 && (i + 1).rem_euclid(64) == 0 {
+            map[i] = map[i - 1];
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct MCAL {
     pub layers: Vec<MCALLayer>,
+    /// Whether the alpha maps hold 8-bit values, rather than 4-bit ones (0 to 15).
+    pub big_alpha: bool,
+}
+
+/// The baked shadow map of the MCNK.
+#[derive(Clone, Debug)]
+pub struct MCSH {
+    /// Whether each of the 64x64 points is in shadow, row by row.
+    pub shadow_map: Vec<bool>,
+}
+
+impl MCSH {
+    /// Whether the point at (x, y) in the 64x64 map is in shadow.
+    pub fn is_shadowed(&self, x: usize, y: usize) -> bool {
+        x < 64 && y < 64 && self.shadow_map[y * 64 + x]
+    }
+}
+
+impl BinRead for MCSH {
+    type Args = (bool,);
+
+    fn read_options<R: Read + Seek>(
+        reader: &mut R,
+        _: &ReadOptions,
+        args: Self::Args,
+    ) -> BinResult<Self> {
+        let (do_not_fix_alpha_map,) = args;
+
+        // One bit per point, starting from the least significant bit.
+        let mut data = [0u8; 64 * 64 / 8];
+        reader.read_exact(&mut data)?;
+        let mut shadow_map: Vec<bool> = data.view_bits::<Lsb0>().iter().by_vals().collect();
+
+        if !do_not_fix_alpha_map {
+            fix_map_edges(&mut shadow_map);
+        }
+
+        Ok(Self {
+            shadow_map,
+        })
+    }
+}
+
+const MCLQ_TILE_TYPE_MASK: u8 = 0x07;
+const MCLQ_TILE_DO_NOT_RENDER: u8 = 0x08;
+const MCLQ_TILE_NOT_LOW_DEPTH: u8 = 0x40;
+const MCLQ_TILE_FATIGUE: u8 = 0x80;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MCLQTile {
+    /// The liquid of the tile (1: ocean, 3: slime, 4: river, 6: magma), see [`MCLQTile::kind`].
+    pub liquid_type: u8,
+    pub do_not_render: bool,
+    /// The tile isn't shallow, as named on wowdev.wiki (MCLQ tile flag 0x40). The client's use of it
+    /// isn't confirmed, so it isn't treated as the fishable flag.
+    pub not_low_depth: bool,
+    /// Swimming in the tile drains fatigue (deep water).
+    pub fatigue: bool,
+
+    /// The undocumented 0x10 and 0x20 bits.
+    pub unknown: u8,
+}
+
+impl MCLQTile {
+    pub fn kind(&self) -> Option<LiquidKind> {
+        match self.liquid_type {
+            1 => Some(LiquidKind::Ocean),
+            3 => Some(LiquidKind::Slime),
+            4 => Some(LiquidKind::Water),
+            6 => Some(LiquidKind::Magma),
+            _ => None,
+        }
+    }
+
+    pub fn to_bits(&self) -> u8 {
+        shared::flags_to_bits(&[
+            (self.do_not_render, MCLQ_TILE_DO_NOT_RENDER),
+            (self.not_low_depth, MCLQ_TILE_NOT_LOW_DEPTH),
+            (self.fatigue, MCLQ_TILE_FATIGUE),
+        ], self.unknown | self.liquid_type & MCLQ_TILE_TYPE_MASK)
+    }
+}
+
+impl BinRead for MCLQTile {
+    type Args = ();
+
+    fn read_options<R: Read + Seek>(
+        reader: &mut R,
+        _: &ReadOptions,
+        _: Self::Args,
+    ) -> BinResult<Self> {
+        let i: u8 = reader.read_le()?;
+
+        Ok(Self {
+            liquid_type: i & MCLQ_TILE_TYPE_MASK,
+            do_not_render: i & MCLQ_TILE_DO_NOT_RENDER == MCLQ_TILE_DO_NOT_RENDER,
+            not_low_depth: i & MCLQ_TILE_NOT_LOW_DEPTH == MCLQ_TILE_NOT_LOW_DEPTH,
+            fatigue: i & MCLQ_TILE_FATIGUE == MCLQ_TILE_FATIGUE,
+            unknown: i & !(MCLQ_TILE_TYPE_MASK | MCLQ_TILE_DO_NOT_RENDER | MCLQ_TILE_NOT_LOW_DEPTH | MCLQ_TILE_FATIGUE),
+        })
+    }
 }
 
 #[derive(BinRead, Clone, Debug)]
@@ -463,31 +1342,351 @@ pub struct MCLQRiverVert {
 #[derive(BinRead, Clone, Debug)]
 #[br(little)]
 pub struct MCLQOceanVert {
-    #[br(map = |d: char| d as u8)]
     pub depth: u8,
-    #[br(map = |d: char| d as u8)]
     pub foam: u8,
-    #[br(map = |d: char| d as u8)]
-    pub filler: u8,
-    #[br(map = |d: char| d as u8)]
     pub wet: u8,
+    // Vertices are stored in 8 bytes, whatever the liquid.
+    #[br(pad_after = 4)]
+    pub filler: u8,
 }
 
+/// A magma or slime vertex, with texture coordinates instead of a depth.
 #[derive(BinRead, Clone, Debug)]
-#[br(little, import(lq_river: bool, lq_ocean: bool, lq_magma: bool))]
-pub struct MCLQ {
+#[br(little)]
+pub struct MCLQMagmaVert {
+    pub s: u16,
+    pub t: u16,
+    pub height: f32,
+}
+
+#[derive(Clone, Debug)]
+pub enum MCLQVerts {
+    River(Vec<MCLQRiverVert>),
+    Ocean(Vec<MCLQOceanVert>),
+    /// Used by both magma and slime.
+    Magma(Vec<MCLQMagmaVert>),
+}
+
+/// A `SWFlowv`, describing how the surface of a river moves.
+#[derive(BinRead, Clone, Debug)]
+#[br(little)]
+pub struct MCLQFlow {
+    pub sphere: shared::CAaSphere,
+    pub direction: shared::C3Vector,
+    pub velocity: f32,
+    pub amplitude: f32,
+    pub frequency: f32,
+}
+
+/// The liquid for one of the liquid flags set on the MCNK.
+#[derive(Clone, Debug)]
+pub struct MCLQLayer {
+    pub kind: LiquidKind,
     pub height: shared::CRange,
+    /// The 9x9 vertices, row by row.
+    pub verts: MCLQVerts,
+    /// The 8x8 tiles, row by row.
+    pub tiles: Vec<MCLQTile>,
+    pub flows: Vec<MCLQFlow>,
+}
+
+impl MCLQLayer {
+    /// The layer as a [`Liquid`], as it would be read from MH2O.
+    pub fn liquid(&self) -> Liquid {
+        let mut heights = [[self.height.min; 9]; 9];
+        let mut depths = [[0; 9]; 9];
+        for i in 0..9 * 9 {
+            let (height, depth) = match &self.verts {
+                MCLQVerts::River(verts) => (verts[i].height, verts[i].depth),
+                MCLQVerts::Ocean(verts) => (self.height.min, verts[i].depth),
+                MCLQVerts::Magma(verts) => (verts[i].height, 0),
+            };
+            heights[i / 9][i % 9] = height;
+            depths[i / 9][i % 9] = depth;
+        }
 
-    #[br(if(lq_river), count=9*9)]
-    pub river_verts: Vec<MCLQRiverVert>,
+        let mut tiles = [[None; 8]; 8];
+        for (i, tile) in self.tiles.iter().enumerate().filter(|(_, tile)| !tile.do_not_render) {
+            tiles[i / 8][i % 8] = Some(LiquidTile { fishable: None, fatigue: tile.fatigue });
+        }
+
+        Liquid {
+            kind: self.kind,
+            liquid_type: None,
+            min_height: self.height.min,
+            max_height: self.height.max,
+            heights,
+            depths,
+            tiles,
+        }
+    }
+}
+
+impl BinRead for MCLQLayer {
+    type Args = (LiquidKind,);
 
-    #[br(if(lq_ocean), count=9*9)]
-    pub ocean_verts: Vec<MCLQOceanVert>,
+    fn read_options<R: Read + Seek>(
+        reader: &mut R,
+        _: &ReadOptions,
+        args: Self::Args,
+    ) -> BinResult<Self> {
+        let height: shared::CRange = reader.read_le()?;
 
-    #[br(if(lq_magma), count=9*9)]
-    pub magma_verts: Vec<MCLQRiverVert>,
+        let verts = match args.0 {
+            LiquidKind::Water => MCLQVerts::River((0..9 * 9).map(|_| reader.read_le()).collect::<BinResult<_>>()?),
+            LiquidKind::Ocean => MCLQVerts::Ocean((0..9 * 9).map(|_| reader.read_le()).collect::<BinResult<_>>()?),
+            LiquidKind::Magma | LiquidKind::Slime => MCLQVerts::Magma((0..9 * 9).map(|_| reader.read_le()).collect::<BinResult<_>>()?),
+        };
 
-    // Only parse if *any* liquid is present in the tile, and convert all chars into u8s.
-    #[br(if(lq_river || lq_ocean || lq_magma), count=8*8, map = |d: Vec<char>| d.iter().map(|d| *d as u8).collect() )]
-    pub tiles: Vec<u8>,
+        let tiles = (0..8 * 8).map(|_| reader.read_le()).collect::<BinResult<Vec<MCLQTile>>>()?;
+
+        // There is always room for two flows, whatever the count says.
+        let n_flowvs: u32 = reader.read_le()?;
+        let mut flows = (0..2).map(|_| reader.read_le()).collect::<BinResult<Vec<MCLQFlow>>>()?;
+        flows.truncate(n_flowvs as usize);
+
+        Ok(Self {
+            kind: args.0,
+            height,
+            verts,
+            tiles,
+            flows,
+        })
+    }
+}
+
+/// The pre-3.x liquids of the MCNK, one layer per liquid flag set, in the order of the flags.
+#[derive(Clone, Debug, Default)]
+pub struct MCLQ {
+    pub layers: Vec<MCLQLayer>,
+}
+
+impl MCLQ {
+    pub fn liquids(&self) -> Vec<Liquid> {
+        self.layers.iter().map(MCLQLayer::liquid).collect()
+    }
+}
+
+impl BinRead for MCLQ {
+    type Args = (MCNKFlags,);
+
+    fn read_options<R: Read + Seek>(
+        reader: &mut R,
+        _: &ReadOptions,
+        args: Self::Args,
+    ) -> BinResult<Self> {
+        let flags = args.0;
+        let kinds = [
+            (flags.lq_river, LiquidKind::Water),
+            (flags.lq_ocean, LiquidKind::Ocean),
+            (flags.lq_magma, LiquidKind::Magma),
+            (flags.lq_slime, LiquidKind::Slime),
+        ];
+
+        let mut layers = Vec::new();
+        for (_, kind) in kinds.into_iter().filter(|(set, _)| *set) {
+            layers.push(reader.read_le_args((kind,))?);
+        }
+
+        Ok(Self {
+            layers,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_liquid() {
+        let mut raw: Vec<u8> = Vec::new();
+        for height in [5.0f32, 6.0] {
+            raw.extend(height.to_le_bytes());
+        }
+        for i in 0..81u8 {
+            raw.extend([i, 0, 0, 0]);
+            raw.extend((5.0 + f32::from(i) / 81.0).to_le_bytes());
+        }
+        raw.extend([0x0F, 0xC4, 0x44, 0x34]);
+        raw.extend([0x04; 60]);
+        raw.extend(1u32.to_le_bytes());
+        for value in [1.0f32, 2.0, 3.0, 4.0, 0.0, 1.0, 0.0, 0.5, 0.25, 2.0] {
+            raw.extend(value.to_le_bytes());
+        }
+        raw.extend([0xFF; 40]);
+
+        let flags = MCNKFlags { lq_river: true, ..Default::default() };
+        let mclq: MCLQ = std::io::Cursor::new(&raw).read_le_args((flags,)).unwrap();
+
+        let layer = &mclq.layers[0];
+        assert_eq!(layer.kind, LiquidKind::Water);
+        assert!(layer.tiles[0].do_not_render);
+        assert_eq!(layer.tiles[1], MCLQTile { liquid_type: 4, do_not_render: false, not_low_depth: true, fatigue: true, unknown: 0 });
+        assert_eq!(layer.tiles[1].kind(), Some(LiquidKind::Water));
+        assert_eq!(layer.tiles[3].unknown, 0x30);
+        assert_eq!(layer.tiles[3].to_bits(), 0x34);
+        assert_eq!(layer.flows.len(), 1);
+        assert_eq!(layer.flows[0].sphere.radius, 4.0);
+        assert_eq!(layer.flows[0].frequency, 2.0);
+
+        let liquid = &mclq.liquids()[0];
+        assert_eq!(liquid.heights[1][0], 5.0 + 9.0 / 81.0);
+        assert_eq!(liquid.depths[8][8], 80);
+        assert_eq!(liquid.tile(0, 0), None);
+        assert_eq!(liquid.tile(2, 0), Some(LiquidTile { fishable: None, fatigue: false }));
+
+        // Slime shares the magma vertices, with texture coordinates instead of depths.
+        let flags = MCNKFlags { lq_slime: true, ..Default::default() };
+        let mclq: MCLQ = std::io::Cursor::new(&raw).read_le_args((flags,)).unwrap();
+        assert_eq!(mclq.layers[0].kind, LiquidKind::Slime);
+        assert!(matches!(&mclq.layers[0].verts, MCLQVerts::Magma(verts) if verts[1].s == 1));
+        assert_eq!(mclq.liquids()[0].depths[0][1], 0);
+    }
+
+    #[test]
+    fn expand_holes() {
+        let holes = low_res_holes(0x8001);
+        assert!(holes[0][0] && holes[0][1] && holes[1][0] && holes[1][1]);
+        assert!(!holes[0][2] && !holes[2][0]);
+        assert!(holes[7][7] && holes[6][6]);
+        assert_eq!(holes.iter().flatten().filter(|hole| **hole).count(), 8);
+
+        let holes = high_res_holes(0x0100_0000_0000_0002);
+        assert!(holes[0][1] && holes[7][0]);
+        assert_eq!(holes.iter().flatten().filter(|hole| **hole).count(), 2);
+    }
+
+    #[test]
+    fn split_heightmap() {
+        let raw_heights: Vec<f32> = (0..145).map(|i| i as f32).collect();
+        let heights = ChunkHeights::new(&raw_heights, shared::C3Vector { x: 100.0, y: 200.0, z: 10.0 });
+
+        assert_eq!(heights.outer[0][8].local.z, 8.0);
+        assert_eq!(heights.outer[1][0].local.z, 17.0);
+        assert_eq!(heights.inner[0][0].local.z, 9.0);
+        assert_eq!(heights.inner[7][7].local.z, 135.0);
+        assert_eq!(heights.outer[8][8].local.z, 144.0);
+
+        let vertex = heights.inner[1][2];
+        assert_eq!(vertex.local.x, 2.5 * QUAD_SIZE);
+        assert_eq!(vertex.local.y, 1.5 * QUAD_SIZE);
+        assert_eq!(vertex.world, shared::C3Vector { x: 100.0 - 1.5 * QUAD_SIZE, y: 200.0 - 2.5 * QUAD_SIZE, z: 10.0 + 28.0 });
+    }
+
+    #[test]
+    fn recompute_normals() {
+        // A slope rising towards the south (decreasing world x) by one unit per quad.
+        let raw_heights: Vec<f32> = (0..145).map(|i| (i / 17) as f32 + if i % 17 > 8 { 0.5 } else { 0.0 }).collect();
+        let heights = ChunkHeights::new(&raw_heights, shared::C3Vector { x: 100.0, y: 200.0, z: 0.0 });
+
+        let normals = heights.normals();
+        let expected = shared::C3Vector { x: 1.0, y: 0.0, z: QUAD_SIZE }.normalized();
+        for normal in normals.outer.iter().flatten().chain(normals.inner.iter().flatten()) {
+            assert!((normal.x - expected.x).abs() < 1e-5 && normal.y.abs() < 1e-5 && (normal.z - expected.z).abs() < 1e-5);
+        }
+
+        let mut mcnr = MCNR { normals: Vec::new(), padding: vec![0; 13] };
+        mcnr.set_normals(&normals);
+        assert_eq!(mcnr.normals.len(), 145);
+        assert_eq!((mcnr.normals[20].x, mcnr.normals[20].y, mcnr.normals[20].z), (30, 0, 123));
+
+        let decoded = mcnr.normals();
+        assert!((decoded.inner[3][4].length() - 1.0).abs() < 1e-5);
+        assert!((decoded.inner[3][4].x - expected.x).abs() < 0.01);
+    }
+
+    #[test]
+    fn parse_sound_emitters() {
+        let mut vanilla: Vec<u8> = Vec::new();
+        vanilla.extend(3u32.to_le_bytes());
+        vanilla.extend(42u32.to_le_bytes());
+        for value in [1.0f32, 2.0, 3.0, 5.0, 10.0, 15.0] {
+            vanilla.extend(value.to_le_bytes());
+        }
+        vanilla.extend([0xFF; 20]);
+
+        let mcse: MCSE = std::io::Cursor::new(&vanilla).read_le_args((1, true)).unwrap();
+        assert_eq!(mcse.emitters[0].sound_entry_id, 42);
+        assert_eq!(mcse.emitters[0].sound_point_id, Some(3));
+        assert_eq!(mcse.emitters[0].size, MCSEEmitterSize::Distances { min_distance: 5.0, max_distance: 10.0, cutoff_distance: 15.0 });
+
+        let mut later: Vec<u8> = Vec::new();
+        for i in 0..2u32 {
+            later.extend(i.to_le_bytes());
+            for value in [1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0] {
+                later.extend(value.to_le_bytes());
+            }
+        }
+
+        let mcse: MCSE = std::io::Cursor::new(&later).read_le_args((2, false)).unwrap();
+        assert_eq!(mcse.emitters.len(), 2);
+        assert_eq!(mcse.emitters[1].sound_entry_id, 1);
+        assert_eq!(mcse.emitters[1].position, shared::C3Vector { x: 1.0, y: 2.0, z: 3.0 });
+        assert_eq!(mcse.emitters[1].size, MCSEEmitterSize::Box(shared::C3Vector { x: 4.0, y: 5.0, z: 6.0 }));
+    }
+
+    #[test]
+    fn layer_animation() {
+        let flags: MCLYFlags = std::io::Cursor::new(0x1000_07D3u32.to_le_bytes()).read_le().unwrap();
+
+        assert!(flags.glow && flags.use_alpha && flags.alpha_compressed && flags.reflection);
+        assert_eq!(flags.animation(), Some(TextureAnimation { direction_degrees: 135.0, speed: 2 }));
+        assert_eq!(flags.unknown, 0x1000_0000);
+        assert_eq!(flags.to_bits(), 0x1000_07D3);
+
+        let still = MCLYFlags { animate_90: true, ..Default::default() };
+        assert_eq!(still.animation(), None);
+    }
+
+    #[test]
+    fn parse_shadow_map() {
+        // The first point of every row is in shadow, as is the second to last column of the first row.
+        let mut raw = [0u8; 512];
+        for row in raw.chunks_mut(8) {
+            row[0] = 0x01;
+        }
+        raw[7] = 0x40;
+
+        let fixed: MCSH = std::io::Cursor::new(&raw).read_le_args((false,)).unwrap();
+        assert!(fixed.is_shadowed(0, 0));
+        assert!(!fixed.is_shadowed(1, 0));
+        assert!(fixed.is_shadowed(62, 0));
+        assert!(fixed.is_shadowed(63, 0));
+        assert!(fixed.is_shadowed(0, 63));
+
+        let unfixed: MCSH = std::io::Cursor::new(&raw).read_le_args((true,)).unwrap();
+        assert!(unfixed.is_shadowed(62, 0));
+        assert!(!unfixed.is_shadowed(63, 0));
+    }
+
+    #[test]
+    fn fix_edges() {
+        let raw: Vec<u8> = (0..4096).map(|i| (i % 251) as u8).collect();
+        let layer: MCALLayer = std::io::Cursor::new(&raw).read_le_args((true, false, false)).unwrap();
+        let alpha_map = &layer.alpha_map;
+
+        assert_eq!(alpha_map[62], raw[62]);
+        assert_eq!(alpha_map[63], raw[62]);
+        assert_eq!(alpha_map[64 * 62 + 63], raw[64 * 62 + 62]);
+        // The first point of the last row is kept, the rest of the row is copied from the row above.
+        assert_eq!(alpha_map[64 * 63], raw[64 * 63]);
+        assert_eq!(alpha_map[64 * 63 + 1], raw[64 * 62 + 1]);
+        assert_eq!(alpha_map[4095], raw[64 * 62 + 62]);
+
+        let layer: MCALLayer = std::io::Cursor::new(&raw).read_le_args((true, false, true)).unwrap();
+        assert_eq!(layer.alpha_map, raw);
+
+        // Shadow maps are fixed the same way.
+        let mut raw = [0u8; 512];
+        raw[8 * 63] = 0x01;
+        raw[8 * 62] = 0x02;
+        raw[8 * 62 + 7] = 0x40;
+        let mcsh: MCSH = std::io::Cursor::new(&raw).read_le_args((false,)).unwrap();
+        assert!(mcsh.is_shadowed(0, 63));
+        assert!(mcsh.is_shadowed(1, 63));
+        assert!(mcsh.is_shadowed(63, 62));
+        assert!(mcsh.is_shadowed(63, 63));
+        assert!(!mcsh.is_shadowed(2, 63));
+    }
 }
@@ -1,6 +1,8 @@
+use std::io::Write;
+
 use binread::BinRead;
 
-use crate::chunks::shared;
+use crate::chunks::shared::{self, ChunkWrite};
 
 
 #[derive(Clone, Debug, PartialEq, BinRead)]
@@ -33,6 +35,17 @@ pub struct MDDFPart {
     pub scale: u16,
     pub flags: MDDFFlags,
 }
+
+impl ChunkWrite for MDDFPart {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&self.name_id.to_le_bytes())?;
+        writer.write_all(&self.unique_id.to_le_bytes())?;
+        self.position.write_data(writer)?;
+        self.rotation.write_data(writer)?;
+        writer.write_all(&self.scale.to_le_bytes())?;
+        writer.write_all(&(self.flags.clone() as u16).to_le_bytes())
+    }
+}
 #[derive(Clone, Debug, BinRead)]
 #[br(little)]
 pub struct MDDF {
@@ -40,6 +53,16 @@ pub struct MDDF {
     pub parts: Vec<MDDFPart>,
 }
 
+impl ChunkWrite for MDDF {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        for part in &self.parts {
+            part.write_data(writer)?;
+        }
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -60,4 +83,11 @@ mod tests {
             flags: MDDFFlags::NONE,
         })
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn write_valid_chunk() {
+        let mut cursor = std::io::Cursor::new(RAW_MDDF);
+        let chunk = cursor.read_le::<MDDFPart>().unwrap();
+        assert_eq!(chunk.to_bytes().unwrap(), RAW_MDDF);
+    }
+}
@@ -0,0 +1,104 @@
+use std::io::Write;
+
+use binread::BinRead;
+
+use crate::chunks::shared::ChunkWrite;
+
+/// A 3x3 grid of heights spanning the whole ADT, row by row from the north-west corner.
+#[derive(Clone, Debug, Default, PartialEq, BinRead)]
+#[br(little)]
+pub struct MFBOPlane {
+    pub heights: [[i16; 3]; 3],
+}
+
+impl MFBOPlane {
+    /// The height at a position in the ADT, as fractions of the tile from its north-west corner.
+    ///
+    /// Heights are bilinearly interpolated between the grid points, and positions outside of
+    /// the tile are clamped to its edges.
+    pub fn height_at(&self, column: f32, row: f32) -> f32 {
+        // The grid points are at the corners, the middle of the edges and the centre of the tile.
+        let column = column.clamp(0.0, 1.0) * 2.0;
+        let row = row.clamp(0.0, 1.0) * 2.0;
+
+        let x = (column.floor() as usize).min(1);
+        let y = (row.floor() as usize).min(1);
+        let (dx, dy) = (column - x as f32, row - y as f32);
+
+        let height = |x: usize, y: usize| f32::from(self.heights[y][x]);
+        let top = height(x, y) * (1.0 - dx) + height(x + 1, y) * dx;
+        let bottom = height(x, y + 1) * (1.0 - dx) + height(x + 1, y + 1) * dx;
+
+        top * (1.0 - dy) + bottom * dy
+    }
+}
+
+impl ChunkWrite for MFBOPlane {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        for height in self.heights.iter().flatten() {
+            writer.write_all(&height.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The flight bounds of the ADT, only present if the MHDR has the MFBO flag set.
+#[derive(Clone, Debug, Default, PartialEq, BinRead)]
+#[br(little)]
+pub struct MFBO {
+    /*
+    struct plane {
+       short[3*3] height;
+    };
+    plane maximum;
+    plane minimum;
+    */
+    /// The ceiling players can't fly above.
+    pub maximum: MFBOPlane,
+    /// The floor players fall through the world below.
+    pub minimum: MFBOPlane,
+}
+
+impl MFBO {
+    /// The flight ceiling at a position in the ADT, see [`MFBOPlane::height_at`].
+    pub fn ceiling_at(&self, column: f32, row: f32) -> f32 {
+        self.maximum.height_at(column, row)
+    }
+
+    /// The flight floor at a position in the ADT, see [`MFBOPlane::height_at`].
+    pub fn floor_at(&self, column: f32, row: f32) -> f32 {
+        self.minimum.height_at(column, row)
+    }
+}
+
+impl ChunkWrite for MFBO {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.maximum.write_data(writer)?;
+        self.minimum.write_data(writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use binread::BinReaderExt;
+
+    #[test]
+    fn interpolate_heights() {
+        let raw: Vec<u8> = [100i16, 200, 300, 100, 200, 300, 500, 500, 500, -10, -10, -10, -10, -10, -10, -10, -10, -10]
+            .iter()
+            .flat_map(|height| height.to_le_bytes())
+            .collect();
+
+        let mfbo: MFBO = std::io::Cursor::new(&raw).read_le().unwrap();
+
+        assert_eq!(mfbo.ceiling_at(0.0, 0.0), 100.0);
+        assert_eq!(mfbo.ceiling_at(0.25, 0.0), 150.0);
+        assert_eq!(mfbo.ceiling_at(1.0, 0.25), 300.0);
+        assert_eq!(mfbo.ceiling_at(0.5, 0.75), 350.0);
+        assert_eq!(mfbo.ceiling_at(2.0, 2.0), 500.0);
+        assert_eq!(mfbo.floor_at(0.3, 0.6), -10.0);
+        assert_eq!(mfbo.to_bytes().unwrap(), raw);
+    }
+}
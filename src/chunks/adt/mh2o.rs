@@ -0,0 +1,440 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use binread::{BinRead, BinReaderExt, BinResult, ReadOptions};
+
+use crate::chunks::adt::{Liquid, LiquidKind, LiquidTile};
+use crate::chunks::shared::ChunkWrite;
+use crate::error::Error;
+
+/// How the vertex data of a liquid instance is laid out.
+///
+/// In 3.3.5 this isn't stored in the ADT, but comes from the LiquidMaterial of the liquid type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LiquidVertexFormat {
+    /// A height and a depth per vertex (water).
+    HeightDepth,
+    /// A height and a texture coordinate per vertex (magma and slime).
+    HeightUV,
+    /// Only a depth per vertex (ocean), the height is always `min_height`.
+    Depth,
+    /// A height, texture coordinate and depth per vertex.
+    HeightUVDepth,
+}
+
+/// Looks up the vertex format of a LiquidType.dbc entry, given to MH2O parsing through [`crate::files::ParseOptions`].
+pub type LiquidVertexFormatLookup = fn(u16) -> LiquidVertexFormat;
+
+impl LiquidVertexFormat {
+    /// A guess at the vertex format of a LiquidType.dbc entry, from its [`LiquidKind`] in the stock 3.3.5 DBC.
+    ///
+    /// Oceans only store depths and magma / slime store texture coordinates, while everything else,
+    /// including any custom entries, is treated as water. [`LiquidVertexFormat::HeightUVDepth`] is never
+    /// guessed. Files using other liquid types need a [`LiquidVertexFormatLookup`] built from the
+    /// client's LiquidType and LiquidMaterial DBCs.
+    pub fn from_liquid_type(liquid_type: u16) -> Self {
+        match LiquidKind::from_liquid_type(liquid_type) {
+            LiquidKind::Ocean => LiquidVertexFormat::Depth,
+            LiquidKind::Magma | LiquidKind::Slime => LiquidVertexFormat::HeightUV,
+            LiquidKind::Water => LiquidVertexFormat::HeightDepth,
+        }
+    }
+
+    fn has_heights(&self) -> bool {
+        !matches!(self, LiquidVertexFormat::Depth)
+    }
+
+    fn has_uvs(&self) -> bool {
+        matches!(self, LiquidVertexFormat::HeightUV | LiquidVertexFormat::HeightUVDepth)
+    }
+
+    fn has_depths(&self) -> bool {
+        !matches!(self, LiquidVertexFormat::HeightUV)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, BinRead)]
+#[br(little)]
+pub struct MH2OUV {
+    pub x: u16,
+    pub y: u16,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MH2OInstance {
+    /*
+    uint16_t liquid_type;             // foreign_keyⁱ<uint16_t, &LiquidTypeRec::m_ID>
+    uint16_t liquid_object_or_lvf;    // LVF from Mists onwards, unused in 3.3.5.
+    float min_height_level;
+    float max_height_level;
+    uint8_t x_offset;
+    uint8_t y_offset;
+    uint8_t width;
+    uint8_t height;
+    uint32_t offset_exists_bitmap;    // not all tiles in the instances need to be filled. always (width * height + 7) / 8 bytes.
+    uint32_t offset_vertex_data;      // actual data format defined by LiquidMaterialRec::m_LVF via LiquidTypeRec::m_materialID
+    */
+    pub liquid_type: u16,
+    pub liquid_object_or_lvf: u16,
+    pub min_height: f32,
+    pub max_height: f32,
+    pub x_offset: u8,
+    pub y_offset: u8,
+    pub width: u8,
+    pub height: u8,
+
+    /// Whether each of the `width * height` tiles of the instance has liquid, row by row.
+    pub exists: Vec<bool>,
+
+    pub vertex_format: LiquidVertexFormat,
+    /// Vertex heights, `(width + 1) * (height + 1)` row by row. Filled with `min_height` if the format has none.
+    pub heights: Vec<f32>,
+    pub depths: Vec<u8>,
+    pub uvs: Vec<MH2OUV>,
+}
+
+impl MH2OInstance {
+    /// Whether the tile at (x, y) in the 8x8 grid of the MCNK has liquid from this instance.
+    pub fn tile_exists(&self, x: u8, y: u8) -> bool {
+        // Widened, as the offsets and sizes come straight from the file and can add up to more than a u8.
+        let (x, y) = (u16::from(x), u16::from(y));
+        let (x_offset, y_offset) = (u16::from(self.x_offset), u16::from(self.y_offset));
+        if x < x_offset || y < y_offset || x >= x_offset + u16::from(self.width) || y >= y_offset + u16::from(self.height) {
+            return false;
+        }
+
+        let i = (y - y_offset) as usize * self.width as usize + (x - x_offset) as usize;
+        self.exists.get(i).copied().unwrap_or(false)
+    }
+
+    /// The instance as a [`Liquid`] covering the whole MCNK, as it would be read from MCLQ.
+    pub fn liquid(&self, attributes: Option<&MH2OAttributes>) -> Liquid {
+        let mut heights = [[self.min_height; 9]; 9];
+        let mut depths = [[0; 9]; 9];
+        let row_length = self.width as usize + 1;
+        for (i, height) in self.heights.iter().enumerate() {
+            let (x, y) = (self.x_offset as usize + i % row_length, self.y_offset as usize + i / row_length);
+            if x < 9 && y < 9 {
+                heights[y][x] = *height;
+                depths[y][x] = self.depths.get(i).copied().unwrap_or(0);
+            }
+        }
+
+        let (fishable, deep) = attributes.map_or((0, 0), |attributes| (attributes.fishable, attributes.deep));
+        let mut tiles = [[None; 8]; 8];
+        for (y, row) in tiles.iter_mut().enumerate() {
+            for (x, tile) in row.iter_mut().enumerate().filter(|(x, _)| self.tile_exists(*x as u8, y as u8)) {
+                let bit = 1u64 << (y * 8 + x);
+                *tile = Some(LiquidTile { fishable: Some(fishable & bit != 0), fatigue: deep & bit != 0 });
+            }
+        }
+
+        Liquid {
+            kind: LiquidKind::from_liquid_type(self.liquid_type),
+            liquid_type: Some(self.liquid_type),
+            min_height: self.min_height,
+            max_height: self.max_height,
+            heights,
+            depths,
+            tiles,
+        }
+    }
+}
+
+impl BinRead for MH2OInstance {
+    /// The vertex format lookup, or `None` for [`LiquidVertexFormat::from_liquid_type`].
+    type Args = (Option<LiquidVertexFormatLookup>,);
+
+    fn args_default() -> Option<Self::Args> {
+        Some((None,))
+    }
+
+    fn read_options<R: Read + Seek>(
+        reader: &mut R,
+        _: &ReadOptions,
+        args: Self::Args,
+    ) -> BinResult<Self> {
+        let liquid_type: u16 = reader.read_le()?;
+        let liquid_object_or_lvf: u16 = reader.read_le()?;
+        let min_height: f32 = reader.read_le()?;
+        let max_height: f32 = reader.read_le()?;
+        let x_offset: u8 = reader.read_le()?;
+        let y_offset: u8 = reader.read_le()?;
+        let width: u8 = reader.read_le()?;
+        let height: u8 = reader.read_le()?;
+        let offset_exists_bitmap: u32 = reader.read_le()?;
+        let offset_vertex_data: u32 = reader.read_le()?;
+
+        let tiles = width as usize * height as usize;
+        let exists = if offset_exists_bitmap == 0 {
+            // Without a bitmap, every tile in the instance has liquid.
+            vec![true; tiles]
+        } else {
+            reader.seek(SeekFrom::Start(offset_exists_bitmap.into()))?;
+            let mut bitmap = vec![0u8; tiles.div_ceil(8)];
+            reader.read_exact(&mut bitmap)?;
+
+            (0..tiles).map(|i| bitmap[i / 8] & (1 << (i % 8)) != 0).collect()
+        };
+
+        let vertex_format = args.0.unwrap_or(LiquidVertexFormat::from_liquid_type)(liquid_type);
+        let vertices = (width as usize + 1) * (height as usize + 1);
+
+        let (mut heights, mut depths, mut uvs) = (Vec::new(), Vec::new(), Vec::new());
+        if offset_vertex_data != 0 {
+            reader.seek(SeekFrom::Start(offset_vertex_data.into()))?;
+
+            if vertex_format.has_heights() {
+                for _ in 0..vertices {
+                    heights.push(reader.read_le()?);
+                }
+            }
+            if vertex_format.has_uvs() {
+                for _ in 0..vertices {
+                    uvs.push(reader.read_le()?);
+                }
+            }
+            if vertex_format.has_depths() {
+                for _ in 0..vertices {
+                    depths.push(reader.read_le()?);
+                }
+            }
+        }
+        if heights.is_empty() {
+            heights = vec![min_height; vertices];
+        }
+
+        Ok(Self {
+            liquid_type,
+            liquid_object_or_lvf,
+            min_height,
+            max_height,
+            x_offset,
+            y_offset,
+            width,
+            height,
+            exists,
+            vertex_format,
+            heights,
+            depths,
+            uvs,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, BinRead)]
+#[br(little)]
+pub struct MH2OAttributes {
+    /*
+    uint64_t fishable;                // seems to be usable as visibility information.
+    uint64_t deep;                    // Might be related to fatigue area if bit set.
+    */
+    pub fishable: u64,
+    pub deep: u64,
+}
+
+/// The liquids of a single MCNK.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MH2OChunk {
+    pub instances: Vec<MH2OInstance>,
+    pub attributes: Option<MH2OAttributes>,
+}
+
+impl MH2OChunk {
+    pub fn liquids(&self) -> Vec<Liquid> {
+        self.instances.iter().map(|instance| instance.liquid(self.attributes.as_ref())).collect()
+    }
+}
+
+impl BinRead for MH2OChunk {
+    type Args = (Option<LiquidVertexFormatLookup>,);
+
+    fn args_default() -> Option<Self::Args> {
+        Some((None,))
+    }
+
+    fn read_options<R: Read + Seek>(
+        reader: &mut R,
+        _: &ReadOptions,
+        args: Self::Args,
+    ) -> BinResult<Self> {
+        /*
+        uint32_t offset_instances;       // points to SMLiquidInstance[layer_count]
+        uint32_t layer_count;            // 0 if the chunk has no liquids. If > 1, the offsets will point to arrays.
+        uint32_t offset_attributes;      // points to mh2o_chunk_attributes, can be ommitted for all-0
+        */
+        let offset_instances: u32 = reader.read_le()?;
+        let layer_count: u32 = reader.read_le()?;
+        let offset_attributes: u32 = reader.read_le()?;
+        let end = reader.stream_position()?;
+        let size = reader.seek(SeekFrom::End(0))?;
+
+        // Not preallocated, as the count comes straight from the file.
+        let mut instances = Vec::new();
+        for i in 0..layer_count {
+            // Each instance header is 24 bytes. The offset comes from the file, so is checked in u64 to avoid overflowing.
+            let offset = u64::from(offset_instances) + u64::from(i) * 24;
+            if offset + 24 > size {
+                return Err(Error::InvalidOffset { location: Box::default(), expected: "MH2O instance" }.into_binread(end));
+            }
+            reader.seek(SeekFrom::Start(offset))?;
+            instances.push(reader.read_le_args(args)?);
+        }
+
+        let attributes = if offset_attributes != 0 && layer_count != 0 {
+            reader.seek(SeekFrom::Start(offset_attributes.into()))?;
+            Some(reader.read_le()?)
+        } else {
+            None
+        };
+
+        reader.seek(SeekFrom::Start(end))?;
+
+        Ok(Self {
+            instances,
+            attributes,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MH2O {
+    /// One entry per MCNK, in the same order as the MCNKs in the ADT.
+    pub chunks: Vec<MH2OChunk>,
+
+    // The chunk is written back as it was read, as its data can be laid out in any order.
+    raw: Vec<u8>,
+}
+
+impl MH2O {
+    /// The liquids of the MCNK at an index, if there are any.
+    pub fn chunk(&self, index: usize) -> Option<&MH2OChunk> {
+        self.chunks.get(index).filter(|chunk| !chunk.instances.is_empty())
+    }
+}
+
+impl BinRead for MH2O {
+    type Args = (Option<LiquidVertexFormatLookup>,);
+
+    fn args_default() -> Option<Self::Args> {
+        Some((None,))
+    }
+
+    fn read_options<R: Read + Seek>(
+        reader: &mut R,
+        _: &ReadOptions,
+        args: Self::Args,
+    ) -> BinResult<Self> {
+        let mut chunks = Vec::with_capacity(16 * 16);
+        for _ in 0..16 * 16 {
+            chunks.push(reader.read_le_args(args)?);
+        }
+
+        let mut raw = Vec::new();
+        reader.seek(SeekFrom::Start(0))?;
+        reader.read_to_end(&mut raw)?;
+
+        Ok(Self {
+            chunks,
+            raw,
+        })
+    }
+}
+
+impl ChunkWrite for MH2O {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&self.raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw_mh2o() -> Vec<u8> {
+        let mut data = vec![0u8; 256 * 12];
+
+        // Chunk 1 has a single 2x1 water instance, with attributes and a bitmap.
+        let instance_offset = data.len() as u32;
+        data[12..24].copy_from_slice(&[instance_offset.to_le_bytes(), 1u32.to_le_bytes(), (instance_offset + 24).to_le_bytes()].concat());
+
+        data.extend(1u16.to_le_bytes());
+        data.extend(0u16.to_le_bytes());
+        data.extend(10.0f32.to_le_bytes());
+        data.extend(12.0f32.to_le_bytes());
+        data.extend([3, 4, 2, 1]);
+        data.extend((instance_offset + 40).to_le_bytes());
+        data.extend((instance_offset + 41).to_le_bytes());
+
+        data.extend(0x1u64.to_le_bytes());
+        data.extend(0x2u64.to_le_bytes());
+
+        data.push(0b01);
+        for height in [10.0f32, 11.0, 12.0, 10.5, 11.5, 12.0] {
+            data.extend(height.to_le_bytes());
+        }
+        data.extend([1, 2, 3, 4, 5, 6]);
+
+        data
+    }
+
+    #[test]
+    fn parse_valid_chunk() {
+        let mut cursor = std::io::Cursor::new(raw_mh2o());
+        let chunk: MH2O = cursor.read_le().unwrap();
+
+        assert!(chunk.chunk(0).is_none());
+
+        let liquid = chunk.chunk(1).unwrap();
+        assert_eq!(liquid.attributes, Some(MH2OAttributes { fishable: 1, deep: 2 }));
+
+        let instance = &liquid.instances[0];
+        assert_eq!(instance.vertex_format, LiquidVertexFormat::HeightDepth);
+        assert_eq!(instance.exists, vec![true, false]);
+        assert!(instance.tile_exists(3, 4));
+        assert!(!instance.tile_exists(4, 4));
+        assert_eq!(instance.heights, vec![10.0, 11.0, 12.0, 10.5, 11.5, 12.0]);
+        assert_eq!(instance.depths, vec![1, 2, 3, 4, 5, 6]);
+
+        let liquids = liquid.liquids();
+        assert_eq!(liquids[0].kind, LiquidKind::Water);
+        assert_eq!(liquids[0].heights[4][3..6], [10.0, 11.0, 12.0]);
+        assert_eq!(liquids[0].heights[5][3..6], [10.5, 11.5, 12.0]);
+        assert_eq!(liquids[0].heights[0][0], 10.0);
+        assert_eq!(liquids[0].depths[5][5], 6);
+        assert_eq!(liquids[0].tile(3, 4), Some(LiquidTile { fishable: Some(false), fatigue: false }));
+        assert_eq!(liquids[0].tile(4, 4), None);
+    }
+
+    #[test]
+    fn look_up_vertex_format() {
+        // Insert texture coordinates between the heights and depths of the instance.
+        let mut data = raw_mh2o();
+        let uvs_offset = 256 * 12 + 41 + 6 * 4;
+        let uvs: Vec<u8> = (0..6u16).flat_map(|i| [i, 2 * i]).flat_map(u16::to_le_bytes).collect();
+        data.splice(uvs_offset..uvs_offset, uvs);
+
+        let lookup: LiquidVertexFormatLookup = |_| LiquidVertexFormat::HeightUVDepth;
+        let chunk: MH2O = std::io::Cursor::new(data).read_le_args((Some(lookup),)).unwrap();
+
+        let instance = &chunk.chunk(1).unwrap().instances[0];
+        assert_eq!(instance.vertex_format, LiquidVertexFormat::HeightUVDepth);
+        assert_eq!(instance.heights[5], 12.0);
+        assert_eq!(instance.uvs[2], MH2OUV { x: 2, y: 4 });
+        assert_eq!(instance.depths, vec![1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn reject_out_of_range_offsets() {
+        let mut instance: MH2OInstance = std::io::Cursor::new(raw_mh2o()).read_le::<MH2O>().unwrap().chunks[1].instances[0].clone();
+        instance.x_offset = 250;
+        instance.width = 10;
+        assert!(!instance.tile_exists(7, 4));
+        assert!(instance.liquid(None).tiles.iter().flatten().all(Option::is_none));
+
+        let mut data = raw_mh2o();
+        data[12..20].copy_from_slice(&[(u32::MAX - 30).to_le_bytes(), 2u32.to_le_bytes()].concat());
+        let error = Error::from(std::io::Cursor::new(data).read_le::<MH2O>().unwrap_err());
+        assert!(matches!(error, Error::InvalidOffset { expected: "MH2O instance", .. }));
+    }
+
+}
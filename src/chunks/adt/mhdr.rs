@@ -1,14 +1,56 @@
-use binread::BinRead;
-
-#[derive(Clone, Debug, BinRead)]
-#[br(little, repr = u32)]
-pub enum MHDRFlags {
-    NONE = 0,
-    MFBO = 1,
-    NORTHREND = 2,
+use std::io::Write;
+
+use binread::{BinRead, BinReaderExt};
+
+use crate::chunks::shared::{self, ChunkWrite};
+use crate::version::ClientVersion;
+
+const MHDR_FLAG_MFBO: u32 = 0x1;
+const MHDR_FLAG_NORTHREND: u32 = 0x2;
+
+#[derive(Clone, Debug, Default)]
+pub struct MHDRFlags {
+    /// The ADT contains a MFBO chunk.
+    pub has_mfbo: bool,
+    /// Set for some of the Northrend ADTs.
+    pub northrend: bool,
+
+    /// Flag bits other than MFBO and Northrend, written back as they were read.
+    pub unknown: u32,
+}
+
+impl MHDRFlags {
+    pub fn to_bits(&self) -> u32 {
+        shared::flags_to_bits(&[
+            (self.has_mfbo, MHDR_FLAG_MFBO),
+            (self.northrend, MHDR_FLAG_NORTHREND),
+        ], self.unknown)
+    }
 }
 
-#[derive(Clone, Debug, BinRead)]
+impl BinRead for MHDRFlags {
+    type Args = ();
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        _: &binread::ReadOptions,
+        _: Self::Args,
+    ) -> binread::BinResult<Self> {
+        let i: u32 = reader.read_le()?;
+
+        Ok(Self {
+            has_mfbo: i & MHDR_FLAG_MFBO == MHDR_FLAG_MFBO,
+            northrend: i & MHDR_FLAG_NORTHREND == MHDR_FLAG_NORTHREND,
+            unknown: i & !(MHDR_FLAG_MFBO | MHDR_FLAG_NORTHREND),
+        })
+    }
+}
+
+/// The ADT header, pointing at the other chunks in the file.
+///
+/// Offsets are relative to the start of the MHDR data (just after its chunk header), and point at
+/// the header of the chunk. An offset of 0 means the chunk isn't present.
+#[derive(Clone, Debug, Default, BinRead)]
 #[br(little)]
 pub struct MHDR {
     /*
@@ -33,4 +75,118 @@ pub struct MHDR {
     uint32_t unused[3];
     */
     pub flags: MHDRFlags,
-}
\ No newline at end of file
+    pub mcin: u32,
+    pub mtex: u32,
+    pub mmdx: u32,
+    pub mmid: u32,
+    pub mwmo: u32,
+    pub mwid: u32,
+    pub mddf: u32,
+    pub modf: u32,
+    /// Only set if `flags.has_mfbo` is.
+    pub mfbo: u32,
+    pub mh2o: u32,
+    pub mtxf: u32,
+
+    // Only used from Cataclysm, kept so they can be written back.
+    mamp_value: u8,
+    padding: [u8; 3],
+    unused: [u32; 3],
+}
+
+impl MHDR {
+    /// The tokens of the chunks MHDR can point at, in the order they are stored.
+    pub const TOKENS: [&'static str; 11] = ["MCIN", "MTEX", "MMDX", "MMID", "MWMO", "MWID", "MDDF", "MODF", "MFBO", "MH2O", "MTXF"];
+
+    /// The tokens of the chunks MHDR can point at in a client version.
+    ///
+    /// MFBO was added in 2.x and MH2O and MTXF in 3.x. Before that, their offsets are padding.
+    pub fn tokens(version: ClientVersion) -> &'static [&'static str] {
+        match version {
+            ClientVersion::Vanilla => &Self::TOKENS[..8],
+            ClientVersion::TBC => &Self::TOKENS[..9],
+            ClientVersion::WotLK => &Self::TOKENS,
+        }
+    }
+
+    /// The offset of a chunk, relative to the MHDR data, if MHDR points at it.
+    pub fn offset(&self, token: &str) -> Option<u32> {
+        let offset = match token {
+            "MCIN" => self.mcin,
+            "MTEX" => self.mtex,
+            "MMDX" => self.mmdx,
+            "MMID" => self.mmid,
+            "MWMO" => self.mwmo,
+            "MWID" => self.mwid,
+            "MDDF" => self.mddf,
+            "MODF" => self.modf,
+            "MFBO" => self.mfbo,
+            "MH2O" => self.mh2o,
+            "MTXF" => self.mtxf,
+            _ => 0,
+        };
+
+        Some(offset).filter(|offset| *offset != 0)
+    }
+
+    /// Sets the offset of a chunk, with `None` for chunks that aren't present.
+    pub fn set_offset(&mut self, token: &str, offset: Option<u32>) {
+        let offset = offset.unwrap_or(0);
+        match token {
+            "MCIN" => self.mcin = offset,
+            "MTEX" => self.mtex = offset,
+            "MMDX" => self.mmdx = offset,
+            "MMID" => self.mmid = offset,
+            "MWMO" => self.mwmo = offset,
+            "MWID" => self.mwid = offset,
+            "MDDF" => self.mddf = offset,
+            "MODF" => self.modf = offset,
+            "MFBO" => self.mfbo = offset,
+            "MH2O" => self.mh2o = offset,
+            "MTXF" => self.mtxf = offset,
+            _ => {},
+        }
+    }
+}
+
+impl ChunkWrite for MHDR {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&self.flags.to_bits().to_le_bytes())?;
+        for token in Self::TOKENS {
+            writer.write_all(&self.offset(token).unwrap_or(0).to_le_bytes())?;
+        }
+
+        writer.write_all(&[self.mamp_value])?;
+        writer.write_all(&self.padding)?;
+        for value in self.unused {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_valid_chunk() {
+        let mut raw: Vec<u8> = Vec::new();
+        for value in [0x3u32, 0x40, 0x1048, 0, 0, 0, 0, 0, 0, 0x2000, 0x2100, 0] {
+            raw.extend(value.to_le_bytes());
+        }
+        raw.extend([0; 16]);
+
+        let mhdr: MHDR = std::io::Cursor::new(&raw).read_le().unwrap();
+
+        assert!(mhdr.flags.has_mfbo);
+        assert!(mhdr.flags.northrend);
+        assert_eq!(mhdr.offset("MCIN"), Some(0x40));
+        assert_eq!(mhdr.offset("MFBO"), Some(0x2000));
+        assert_eq!(mhdr.offset("MMDX"), None);
+        assert!(!MHDR::tokens(ClientVersion::Vanilla).contains(&"MFBO"));
+        assert_eq!(MHDR::tokens(ClientVersion::TBC).last(), Some(&"MFBO"));
+        assert_eq!(mhdr.to_bytes().unwrap(), raw);
+    }
+}
@@ -1,3 +1,5 @@
+use std::io::Write;
+
 use binread::BinRead;
 
 use crate::chunks::shared;
@@ -10,4 +12,17 @@ pub struct MMDX {
     */
     #[br(parse_with = shared::zero_terminated_strings)]
     pub filenames: Vec<String>,
-}
\ No newline at end of file
+}
+
+impl MMDX {
+    /// The filename starting at an offset from MMID.
+    pub fn filename_at(&self, offset: u32) -> Option<&str> {
+        shared::string_at_offset(&self.filenames, offset)
+    }
+}
+
+impl shared::ChunkWrite for MMDX {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        shared::write_zero_terminated_strings(writer, &self.filenames)
+    }
+}
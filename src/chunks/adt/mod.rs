@@ -1,17 +1,25 @@
+mod liquid;
 mod mcin;
 mod mcnk;
 mod mddf;
+mod mfbo;
+mod mh2o;
 mod mhdr;
 mod mmdx;
 mod mmid;
 mod mtex;
+mod mtxf;
 mod mwid;
 
+pub use liquid::*;
 pub use mcin::*;
 pub use mcnk::*;
 pub use mddf::*;
+pub use mfbo::*;
+pub use mh2o::*;
 pub use mhdr::*;
 pub use mmdx::*;
 pub use mmid::*;
 pub use mtex::*;
+pub use mtxf::*;
 pub use mwid::*;
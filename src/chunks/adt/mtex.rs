@@ -1,3 +1,5 @@
+use std::io::Write;
+
 use binread::BinRead;
 
 use crate::chunks::shared;
@@ -11,3 +13,9 @@ pub struct MTEX {
     #[br(parse_with = shared::zero_terminated_strings)]
     pub filenames: Vec<String>,
 }
+
+impl shared::ChunkWrite for MTEX {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        shared::write_zero_terminated_strings(writer, &self.filenames)
+    }
+}
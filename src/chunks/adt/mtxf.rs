@@ -0,0 +1,94 @@
+use std::io::Write;
+
+use binread::{BinRead, BinReaderExt};
+
+use crate::chunks::shared::{self, ChunkWrite};
+
+const MTXF_FLAG_DO_NOT_LOAD_SPECULAR_OR_HEIGHT: u32 = 0x1;
+const MTXF_TEXTURE_SCALE_MASK: u32 = 0xF0;
+const MTXF_TEXTURE_SCALE_SHIFT: u32 = 4;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MTXFFlags {
+    /// Don't load the `_s` / `_h` textures, and use the terrain cubemap instead.
+    pub do_not_load_specular_or_height: bool,
+    /// Scale of the texture as a power of two, only used from Mists of Pandaria.
+    pub texture_scale: u8,
+
+    /// Bits besides the specular / height flag and the texture scale, kept for writing the MTXF back.
+    pub unknown: u32,
+}
+
+impl MTXFFlags {
+    pub fn to_bits(&self) -> u32 {
+        let scale = (u32::from(self.texture_scale) << MTXF_TEXTURE_SCALE_SHIFT) & MTXF_TEXTURE_SCALE_MASK;
+
+        shared::flags_to_bits(&[
+            (self.do_not_load_specular_or_height, MTXF_FLAG_DO_NOT_LOAD_SPECULAR_OR_HEIGHT),
+        ], self.unknown | scale)
+    }
+}
+
+impl BinRead for MTXFFlags {
+    type Args = ();
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        _: &binread::ReadOptions,
+        _: Self::Args,
+    ) -> binread::BinResult<Self> {
+        let i: u32 = reader.read_le()?;
+
+        Ok(Self {
+            do_not_load_specular_or_height: i & MTXF_FLAG_DO_NOT_LOAD_SPECULAR_OR_HEIGHT == MTXF_FLAG_DO_NOT_LOAD_SPECULAR_OR_HEIGHT,
+            texture_scale: ((i & MTXF_TEXTURE_SCALE_MASK) >> MTXF_TEXTURE_SCALE_SHIFT) as u8,
+            unknown: i & !(MTXF_FLAG_DO_NOT_LOAD_SPECULAR_OR_HEIGHT | MTXF_TEXTURE_SCALE_MASK),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, BinRead)]
+#[br(little)]
+pub struct MTXF {
+    /*
+    uint32_t flags[0];              // one per MTEX entry.
+    */
+    #[br(parse_with = shared::read_until_end)]
+    pub flags: Vec<MTXFFlags>,
+}
+
+impl ChunkWrite for MTXF {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        for flags in &self.flags {
+            writer.write_all(&flags.to_bits().to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A MTEX entry joined with its MTXF flags.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Texture<'a> {
+    pub filename: &'a str,
+    /// The flags of the texture, or the defaults if the ADT has no MTXF entry for it.
+    pub flags: MTXFFlags,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_valid_chunk() {
+        let raw: Vec<u8> = [0x0u32, 0x1, 0x8031].iter().flat_map(|flags| flags.to_le_bytes()).collect();
+        let mtxf: MTXF = std::io::Cursor::new(&raw).read_le().unwrap();
+
+        assert_eq!(mtxf.flags.len(), 3);
+        assert!(!mtxf.flags[0].do_not_load_specular_or_height);
+        assert!(mtxf.flags[1].do_not_load_specular_or_height);
+        assert_eq!(mtxf.flags[2].texture_scale, 3);
+        assert_eq!(mtxf.flags[2].unknown, 0x8000);
+        assert_eq!(mtxf.to_bytes().unwrap(), raw);
+    }
+}
@@ -1,3 +1,5 @@
+use std::io::Write;
+
 use binread::BinRead;
 
 use crate::chunks::shared;
@@ -10,4 +12,14 @@ pub struct MWID {
     */
     #[br(parse_with = shared::read_until_end)]
     pub offsets: Vec<u32>,
-}
\ No newline at end of file
+}
+
+impl shared::ChunkWrite for MWID {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        for offset in &self.offsets {
+            writer.write_all(&offset.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,14 +1,87 @@
 use core::fmt::Debug;
-use std::io::{Read, Seek};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
 use binread::{BinRead, BinReaderExt, BinResult, ReadOptions};
 
-#[derive(Clone, Debug, BinRead)]
+/// Serialises the body of a chunk, the inverse of its [`BinRead`] implementation.
+pub trait ChunkWrite {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()>;
+
+    fn to_bytes(&self) -> std::io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.write_data(&mut data)?;
+
+        Ok(data)
+    }
+}
+
+/// Writes a chunk header with the given token and size, stored reversed like in the files themselves.
+pub fn write_chunk_header<W: Write>(writer: &mut W, token: &str, size: u32) -> std::io::Result<()> {
+    let token: Vec<u8> = token.chars().rev().map(|c| c as u8).collect();
+    writer.write_all(&token)?;
+    writer.write_all(&size.to_le_bytes())
+}
+
+/// Writes a full chunk, returning the offset its header was written at.
+pub fn write_chunk<W: Write + Seek>(writer: &mut W, token: &str, data: &[u8]) -> std::io::Result<u64> {
+    let offset = writer.stream_position()?;
+    write_chunk_header(writer, token, data.len() as u32)?;
+    writer.write_all(data)?;
+
+    Ok(offset)
+}
+
+/// Size of an ADT tile in world units (yards).
+pub const ADT_SIZE: f32 = 533.0 + (1.0 / 3.0);
+/// Size of an MCNK in world units, each ADT has 16x16 of them.
+pub const CHUNK_SIZE: f32 = ADT_SIZE / 16.0;
+
+/// World position of the north-west corner of the ADT tile at (x, y).
+///
+/// The tile x coordinate runs along the world Y axis and the tile y along the world X axis,
+/// both decreasing away from the corner.
+pub fn tile_corner(x: u32, y: u32) -> (f32, f32) {
+    ((32.0 - y as f32) * ADT_SIZE, (32.0 - x as f32) * ADT_SIZE)
+}
+
+/// The (x, y) of the ADT tile containing a world position, or `None` if it is outside of the map.
+pub fn tile_at(world_x: f32, world_y: f32) -> Option<(u32, u32)> {
+    let x = (32.0 - world_y / ADT_SIZE).floor();
+    let y = (32.0 - world_x / ADT_SIZE).floor();
+
+    let range = 0.0..64.0;
+    (range.contains(&x) && range.contains(&y)).then_some((x as u32, y as u32))
+}
+
+/// A colour stored in BGRA order.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, BinRead)]
+#[br(little)]
+pub struct CImVector {
+    pub b: u8,
+    pub g: u8,
+    pub r: u8,
+    pub a: u8,
+}
+
+impl ChunkWrite for CImVector {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&[self.b, self.g, self.r, self.a])
+    }
+}
+
+#[derive(Clone, Debug, Default, BinRead)]
 #[br(little)]
 pub struct CRange {
     pub min: f32,
     pub max: f32,
 }
 
+#[derive(Clone, Copy, PartialEq, Debug, BinRead)]
+#[br(little)]
+pub struct CAaSphere {
+    pub center: C3Vector,
+    pub radius: f32,
+}
+
 #[derive(Clone, Debug, BinRead)]
 #[br(little)]
 pub struct CAaBox {
@@ -24,7 +97,46 @@ pub struct C3Vector {
     pub z: f32,
 }
 
-pub fn char_vec_to_string_le(v: &Vec<u8>, reversed: bool) -> String {
+impl C3Vector {
+    pub fn cross(&self, other: &C3Vector) -> C3Vector {
+        C3Vector {
+            x: self.y * other.z - self.z * other.y,
+            y: self.z * other.x - self.x * other.z,
+            z: self.x * other.y - self.y * other.x,
+        }
+    }
+
+    pub fn length(&self) -> f32 {
+        (self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
+    }
+
+    /// The vector scaled to a length of 1, or unchanged if its length is 0.
+    pub fn normalized(&self) -> C3Vector {
+        let length = self.length();
+        if length == 0.0 {
+            return *self;
+        }
+
+        C3Vector { x: self.x / length, y: self.y / length, z: self.z / length }
+    }
+}
+
+impl ChunkWrite for C3Vector {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&self.x.to_le_bytes())?;
+        writer.write_all(&self.y.to_le_bytes())?;
+        writer.write_all(&self.z.to_le_bytes())
+    }
+}
+
+impl ChunkWrite for CAaBox {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.min.write_data(writer)?;
+        self.max.write_data(writer)
+    }
+}
+
+pub fn char_vec_to_string_le(v: &[u8], reversed: bool) -> String {
     if reversed {
         v.iter().rev().map(|v| char::from(*v)).collect::<String>()
     } else {
@@ -32,7 +144,6 @@ pub fn char_vec_to_string_le(v: &Vec<u8>, reversed: bool) -> String {
     }
 }
 
-
 pub fn zero_terminated_strings<R: Read + Seek>(
     reader: &mut R,
     _: &ReadOptions,
@@ -41,23 +152,45 @@ pub fn zero_terminated_strings<R: Read + Seek>(
     let mut strings: Vec<String> = Vec::new();
     let mut string_buf: Vec<u8> = Vec::new();
 
-    loop {
-        match reader.read_le::<u8>() {
-            Ok(v) => {
-                if v != u8::MIN {
-                    string_buf.push(v);
-                } else {
-                    strings.push(char_vec_to_string_le(&string_buf, false));
-                    string_buf.clear();
-                }
-            }
-            Err(_) => break,
+    while let Ok(v) = reader.read_le::<u8>() {
+        if v != u8::MIN {
+            string_buf.push(v);
+        } else {
+            strings.push(char_vec_to_string_le(&string_buf, false));
+            string_buf.clear();
         }
     }
 
     Ok(strings)
 }
 
+/// Finds the string starting at a byte offset into a list of zero-terminated strings,
+/// as referenced by chunks like MMID and MWID.
+pub fn string_at_offset(strings: &[String], offset: u32) -> Option<&str> {
+    let mut start = 0;
+    for string in strings {
+        if start == offset as usize {
+            return Some(string);
+        }
+
+        // Each character was read from a single byte, followed by the terminator.
+        start += string.chars().count() + 1;
+    }
+
+    None
+}
+
+/// Writes strings as parsed by [`zero_terminated_strings`], each followed by a null byte.
+pub fn write_zero_terminated_strings<W: Write>(writer: &mut W, strings: &[String]) -> std::io::Result<()> {
+    for string in strings {
+        let bytes: Vec<u8> = string.chars().map(|c| c as u8).collect();
+        writer.write_all(&bytes)?;
+        writer.write_all(&[0])?;
+    }
+
+    Ok(())
+}
+
 pub fn read_until_end<R: Read + Seek, T: BinRead>(
     reader: &mut R,
     _: &ReadOptions,
@@ -65,18 +198,32 @@ pub fn read_until_end<R: Read + Seek, T: BinRead>(
 ) -> BinResult<Vec<T>> {
     let mut values: Vec<T> = Vec::new();
 
-    loop {
-        match reader.read_le::<T>() {
-            Ok(v) => {
-                values.push(v);
-            }
-            Err(_) => break,
-        }
+    while let Ok(v) = reader.read_le::<T>() {
+        values.push(v);
     }
 
     Ok(values)
 }
 
+/// Packs flags decoded into bools back into their bits, on top of the bits that weren't decoded.
+pub(crate) fn flags_to_bits<T: Copy + std::ops::BitOr<Output = T>>(flags: &[(bool, T)], unknown: T) -> T {
+    flags.iter().fold(unknown, |bits, &(set, flag)| if set { bits | flag } else { bits })
+}
+
+/// Reads exactly `len` bytes.
+///
+/// Unlike allocating a buffer up front, a corrupt length can't allocate more than is left in the reader.
+pub(crate) fn read_bytes<R: Read>(reader: &mut R, len: u64) -> std::io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.take(len).read_to_end(&mut data)?;
+
+    if (data.len() as u64) < len {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(data)
+}
+
 pub fn token_parse<R: Read + Seek>(reader: &mut R, _ro: &ReadOptions, _: ()) -> BinResult<String> {
     // Read 4 u8s into a buffer.
     let mut token = vec![0; 4];
@@ -98,6 +245,186 @@ pub struct ChunkWrapper {
     pub data: Vec<u8>,
 }
 
+/// The header of a single chunk, as yielded by [`ChunkIter`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChunkHeader {
+    pub token: String,
+    /// Absolute offset of the chunk header in the source.
+    pub offset: u64,
+    /// Size of the chunk body, not including the header itself.
+    pub size: u32,
+}
+
+impl ChunkHeader {
+    /// Size of the token and size fields preceding every chunk body.
+    pub const SIZE: u64 = 8;
+
+    /// Absolute offset of the first byte of the chunk body.
+    pub fn data_offset(&self) -> u64 {
+        self.offset + Self::SIZE
+    }
+
+    /// Absolute offset of the first byte after the chunk body.
+    pub fn end_offset(&self) -> u64 {
+        self.data_offset() + u64::from(self.size)
+    }
+}
+
+/// Lazily walks the chunks of any seekable source, starting from its current position.
+///
+/// Only the chunk headers are read while iterating. Bodies are read on request with
+/// [`ChunkIter::read_data`] or [`ChunkIter::parse_data`], otherwise they are skipped.
+///
+/// ```no_run
+/// let file = std::fs::File::open("./test_data/Azeroth/Azeroth_31_30.adt").unwrap();
+/// let mut chunks = wow_chunky::chunks::shared::ChunkIter::new(file).unwrap();
+///
+/// while let Some(header) = chunks.next() {
+///     let header = header.unwrap();
+///     if header.token == "MDDF" {
+///         let mddf: wow_chunky::chunks::adt::MDDF = chunks.parse_data(&header, ()).unwrap();
+///     }
+/// }
+/// ```
+pub struct ChunkIter<R> {
+    reader: R,
+    next_offset: u64,
+    finished: bool,
+}
+
+impl<R: Read + Seek> ChunkIter<R> {
+    pub fn new(mut reader: R) -> BinResult<Self> {
+        let next_offset = reader.stream_position()?;
+
+        Ok(Self {
+            reader,
+            next_offset,
+            finished: false,
+        })
+    }
+
+    /// Reads the body of a chunk into a buffer.
+    pub fn read_data(&mut self, header: &ChunkHeader) -> BinResult<Vec<u8>> {
+        self.reader.seek(SeekFrom::Start(header.data_offset()))?;
+
+        Ok(read_bytes(&mut self.reader, header.size.into())?)
+    }
+
+    /// Reads the body of a chunk and parses it as `T`.
+    ///
+    /// Parsing is bounded to the chunk body, so chunks that read until the end of
+    /// their data (e.g. string lists) stop at the end of the chunk.
+    pub fn parse_data<T: BinRead>(&mut self, header: &ChunkHeader, args: T::Args) -> BinResult<T> {
+        let data = self.read_data(header)?;
+        Cursor::new(data).read_le_args(args)
+    }
+
+    /// Reads the header of the chunk at an absolute offset, without moving the iterator.
+    ///
+    /// Useful for jumping straight to a chunk through an offset stored in another chunk, such as MCIN.
+    pub fn header_at(&mut self, offset: u64) -> BinResult<ChunkHeader> {
+        self.read_header_at(offset)?
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into())
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn read_header(&mut self) -> BinResult<Option<ChunkHeader>> {
+        let header = self.read_header_at(self.next_offset)?;
+        if let Some(header) = &header {
+            self.next_offset = header.end_offset();
+        }
+
+        Ok(header)
+    }
+
+    fn read_header_at(&mut self, offset: u64) -> BinResult<Option<ChunkHeader>> {
+        self.reader.seek(SeekFrom::Start(offset))?;
+
+        let mut buf = [0u8; ChunkHeader::SIZE as usize];
+        let mut read = 0;
+        while read < buf.len() {
+            match self.reader.read(&mut buf[read..])? {
+                0 => break,
+                n => read += n,
+            }
+        }
+
+        // A clean end of the source, rather than a truncated header.
+        if read == 0 {
+            return Ok(None);
+        }
+        if read < buf.len() {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+
+        let token = char_vec_to_string_le(&buf[..4], true);
+        let size = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]);
+
+        Ok(Some(ChunkHeader {
+            token,
+            offset,
+            size,
+        }))
+    }
+}
+
+impl<R: Read + Seek> Iterator for ChunkIter<R> {
+    type Item = BinResult<ChunkHeader>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let header = self.read_header();
+        if !matches!(header, Ok(Some(_))) {
+            self.finished = true;
+        }
+
+        header.transpose()
+    }
+}
+
+/// Wraps the error from a subchunk that failed to parse, so the parent chunk can report which one it was.
+///
+/// Stored in a [`binread::Error::Custom`].
+#[derive(Debug)]
+pub struct SubchunkError {
+    pub token: &'static str,
+    /// Offset of the subchunk header, relative to the start of the parent chunk.
+    pub offset: u32,
+    pub source: binread::Error,
+}
+
+impl SubchunkError {
+    pub fn wrap(token: &'static str, offset: u32) -> impl FnOnce(binread::Error) -> binread::Error {
+        move |source| binread::Error::Custom {
+            pos: offset.into(),
+            err: Box::new(SubchunkError { token, offset, source }),
+        }
+    }
+}
+
+/// A chunk with a token the parser does not recognise, kept as raw bytes so
+/// it can be inspected (or written back) later.
+#[derive(Clone, Debug)]
+pub struct RawChunk {
+    pub token: String,
+    /// Absolute offset of the chunk header in the source file.
+    pub offset: u64,
+    pub data: Vec<u8>,
+}
+
+impl ChunkWrite for RawChunk {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&self.data)
+    }
+}
+
 #[derive(Clone, Debug, BinRead)]
 #[br(little)]
 pub struct MVER {
@@ -107,6 +434,12 @@ pub struct MVER {
     pub version: u32,
 }
 
+impl ChunkWrite for MVER {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&self.version.to_le_bytes())
+    }
+}
+
 #[derive(Clone, Debug, BinRead)]
 #[br(little)]
 pub struct MWMO {
@@ -117,6 +450,19 @@ pub struct MWMO {
     pub filenames: Vec<String>,
 }
 
+impl MWMO {
+    /// The filename starting at an offset from MWID.
+    pub fn filename_at(&self, offset: u32) -> Option<&str> {
+        string_at_offset(&self.filenames, offset)
+    }
+}
+
+impl ChunkWrite for MWMO {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        write_zero_terminated_strings(writer, &self.filenames)
+    }
+}
+
 #[derive(Clone, Debug, BinRead)]
 #[br(little, repr = u16)]
 pub enum MODFFlags {
@@ -143,10 +489,24 @@ pub struct MODFPart {
     pub position: C3Vector,
     pub rotation: C3Vector,
     pub extends: CAaBox,
-    pub scale: u16,
     pub flags: MODFFlags,
     pub doodat_set: u16,
     pub name_set: u16,
+    pub scale: u16,
+}
+
+impl ChunkWrite for MODFPart {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&self.name_id.to_le_bytes())?;
+        writer.write_all(&self.unique_id.to_le_bytes())?;
+        self.position.write_data(writer)?;
+        self.rotation.write_data(writer)?;
+        self.extends.write_data(writer)?;
+        writer.write_all(&(self.flags.clone() as u16).to_le_bytes())?;
+        writer.write_all(&self.doodat_set.to_le_bytes())?;
+        writer.write_all(&self.name_set.to_le_bytes())?;
+        writer.write_all(&self.scale.to_le_bytes())
+    }
 }
 
 #[derive(Clone, Debug, BinRead)]
@@ -156,3 +516,72 @@ pub struct MODF {
     pub parts: Vec<MODFPart>,
 }
 
+impl ChunkWrite for MODF {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        for part in &self.parts {
+            part.write_data(writer)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RAW_CHUNKS: [u8; 24] = [
+        0x52, 0x45, 0x56, 0x4D, 0x04, 0x00, 0x00, 0x00, 0x12, 0x00, 0x00, 0x00, // MVER, version 18
+        0x58, 0x45, 0x54, 0x4D, 0x04, 0x00, 0x00, 0x00, 0x61, 0x62, 0x63, 0x00, // MTEX, "abc"
+    ];
+
+    #[test]
+    fn iterate_chunks() {
+        let mut chunks = ChunkIter::new(Cursor::new(RAW_CHUNKS)).unwrap();
+
+        let mver = chunks.next().unwrap().unwrap();
+        let mtex = chunks.next().unwrap().unwrap();
+        assert!(chunks.next().is_none());
+
+        assert_eq!(mver, ChunkHeader { token: "MVER".to_string(), offset: 0, size: 4 });
+        assert_eq!(mtex, ChunkHeader { token: "MTEX".to_string(), offset: 12, size: 4 });
+
+        let version: MVER = chunks.parse_data(&mver, ()).unwrap();
+        assert_eq!(version.version, 18);
+        assert_eq!(chunks.read_data(&mtex).unwrap(), vec![0x61, 0x62, 0x63, 0x00]);
+    }
+
+    #[test]
+    fn truncated_header() {
+        let mut chunks = ChunkIter::new(Cursor::new(&RAW_CHUNKS[..16])).unwrap();
+
+        assert!(chunks.next().unwrap().is_ok());
+        assert!(chunks.next().unwrap().is_err());
+        assert!(chunks.next().is_none());
+    }
+
+    #[test]
+    fn modf_layout() {
+        let mut raw: Vec<u8> = Vec::new();
+        for value in [1u32, 2] {
+            raw.extend(value.to_le_bytes());
+        }
+        for value in [3.0f32, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11.0, 12.0, 13.0, 14.0] {
+            raw.extend(value.to_le_bytes());
+        }
+        for value in [1u16, 15, 16, 1024] {
+            raw.extend(value.to_le_bytes());
+        }
+        assert_eq!(raw.len(), 64);
+
+        let modf: MODF = Cursor::new(&raw).read_le().unwrap();
+        let part = &modf.parts[0];
+        assert_eq!((part.name_id, part.unique_id), (1, 2));
+        assert_eq!(part.position.x, 3.0);
+        assert_eq!(part.rotation.z, 8.0);
+        assert_eq!((part.extends.min.x, part.extends.max.z), (9.0, 14.0));
+        assert!(matches!(part.flags, MODFFlags::DESTROYABLE));
+        assert_eq!((part.doodat_set, part.name_set, part.scale), (15, 16, 1024));
+        assert_eq!(modf.to_bytes().unwrap(), raw);
+    }
+}
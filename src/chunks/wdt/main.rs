@@ -1,17 +1,88 @@
 use core::fmt::Debug;
+use std::io::Write;
 
-use binread::BinRead;
+use binread::{BinRead, BinReaderExt};
+
+use crate::chunks::shared::{self, ChunkWrite};
+
+const MAIN_FLAG_HAS_ADT: u32 = 0x1;
+const MAIN_FLAG_ALL_WATER: u32 = 0x2;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MAINTileFlags {
+    /// There is an ADT file for the tile.
+    pub has_adt: bool,
+    /// The tile is entirely covered in water.
+    pub all_water: bool,
+
+    /// Any other bits of the tile flags. Only the two above are used up to 3.3.5, so this is normally 0.
+    pub unknown: u32,
+}
+
+impl MAINTileFlags {
+    pub fn to_bits(&self) -> u32 {
+        shared::flags_to_bits(&[
+            (self.has_adt, MAIN_FLAG_HAS_ADT),
+            (self.all_water, MAIN_FLAG_ALL_WATER),
+        ], self.unknown)
+    }
+}
+
+impl BinRead for MAINTileFlags {
+    type Args = ();
+
+    fn read_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        _: &binread::ReadOptions,
+        _: Self::Args,
+    ) -> binread::BinResult<Self> {
+        let i: u32 = reader.read_le()?;
+
+        Ok(Self {
+            has_adt: i & MAIN_FLAG_HAS_ADT == MAIN_FLAG_HAS_ADT,
+            all_water: i & MAIN_FLAG_ALL_WATER == MAIN_FLAG_ALL_WATER,
+            unknown: i & !(MAIN_FLAG_HAS_ADT | MAIN_FLAG_ALL_WATER),
+        })
+    }
+}
 
 #[derive(Clone, Debug, BinRead)]
 #[br(little)]
 pub struct MAINTile {
-    pub has_adt: u32,
-    pub flag_loaded: u32,
+    /*
+    uint32_t flags;
+    uint32_t asyncId;              // only set during runtime.
+    */
+    pub flags: MAINTileFlags,
+    pub async_id: u32,
 }
 
 #[derive(Clone, Debug, BinRead)]
 #[br(little)]
 pub struct MAIN {
+    /// The 64x64 tiles of the map, row by row (`y * 64 + x`).
     #[br(count = 4096)]
     pub tiles: Vec<MAINTile>,
 }
+
+impl MAIN {
+    /// The tile at (x, y), if the coordinates are within the 64x64 grid.
+    pub fn tile(&self, x: u32, y: u32) -> Option<&MAINTile> {
+        if x >= 64 || y >= 64 {
+            return None;
+        }
+
+        self.tiles.get((y * 64 + x) as usize)
+    }
+}
+
+impl ChunkWrite for MAIN {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        for tile in &self.tiles {
+            writer.write_all(&tile.flags.to_bits().to_le_bytes())?;
+            writer.write_all(&tile.async_id.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+}
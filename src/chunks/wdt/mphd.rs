@@ -1,7 +1,10 @@
 use core::fmt::Debug;
+use std::io::Write;
 
 use binread::{BinRead, BinReaderExt};
 
+use crate::chunks::shared::{self, ChunkWrite};
+
 const MPHD_FLAG_USES_GLOBAL_MAP_OBJ: u32 = 0x01;
 const MPHD_FLAG_ADT_HAS_MCCV: u32 = 0x2;
 const MPHD_FLAG_ADT_HAS_BIG_ALPHA: u32 = 0x4;
@@ -12,9 +15,50 @@ const MPHD_FLAG_UNK: u32 = 0x40;
 const MPHD_FLAG_ADT_HAS_HEIGHT_TEXTURING: u32 = 0x80;
 
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug, Default)]
 pub struct MPHDFlags {
-    pub has_height_texturing: bool,
+    /// The map is a single global WMO, with no ADTs.
+    pub uses_global_map_obj: bool,
+    /// The ADTs have MCCV vertex colours.
+    pub adt_has_mccv: bool,
+    /// The ADTs store 8-bit alpha maps instead of 4-bit ones.
+    pub adt_has_big_alpha: bool,
+    /// The MCRF doodad references are sorted by size category.
+    pub adt_has_doodads_sorted_by_size: bool,
+    /// The ADTs have MCLV vertex lighting (Cataclysm+).
+    pub adt_has_lighting_vertices: bool,
+    pub adt_has_upside_down_ground: bool,
+    pub unk_0x40: bool,
+    /// The ADTs use height based texture blending, which also implies big alpha maps.
+    pub adt_has_height_texturing: bool,
+
+    /// Bits from 0x100 up, which are only used after 3.3.5, kept so the WDT can be written back unchanged.
+    pub unknown: u32,
+}
+
+impl MPHDFlags {
+    /// Whether any flags only used from 3.x onwards are set (vertex colours or big alpha maps).
+    pub(crate) fn has_wotlk_flags(&self) -> bool {
+        self.adt_has_mccv || self.adt_has_big_alpha
+    }
+
+    /// Whether the MCAL alpha maps are stored with 8 bits per value (4096 bytes uncompressed), rather than 4.
+    pub fn uses_big_alpha(&self) -> bool {
+        self.adt_has_big_alpha || self.adt_has_height_texturing
+    }
+
+    pub fn to_bits(&self) -> u32 {
+        shared::flags_to_bits(&[
+            (self.uses_global_map_obj, MPHD_FLAG_USES_GLOBAL_MAP_OBJ),
+            (self.adt_has_mccv, MPHD_FLAG_ADT_HAS_MCCV),
+            (self.adt_has_big_alpha, MPHD_FLAG_ADT_HAS_BIG_ALPHA),
+            (self.adt_has_doodads_sorted_by_size, MPHD_FLAG_ADT_HAS_DOODADS_SORTED_BY_SIZE),
+            (self.adt_has_lighting_vertices, MPHD_FLAG_LIGHTING_VERTICES),
+            (self.adt_has_upside_down_ground, MPHD_FLAG_UPSIDE_DOWN_GROUND),
+            (self.unk_0x40, MPHD_FLAG_UNK),
+            (self.adt_has_height_texturing, MPHD_FLAG_ADT_HAS_HEIGHT_TEXTURING),
+        ], self.unknown)
+    }
 }
 
 impl BinRead for MPHDFlags {
@@ -22,31 +66,50 @@ impl BinRead for MPHDFlags {
 
     fn read_options<R: std::io::Read + std::io::Seek>(
         reader: &mut R,
-        options: &binread::ReadOptions,
-        args: Self::Args,
+        _: &binread::ReadOptions,
+        _: Self::Args,
     ) -> binread::BinResult<Self> {
         let i: u32 = reader.read_le()?;
 
-        let unk = i & MPHD_FLAG_UNK == MPHD_FLAG_UNK;
-        let has_height_texturing = i & MPHD_FLAG_ADT_HAS_HEIGHT_TEXTURING == MPHD_FLAG_ADT_HAS_HEIGHT_TEXTURING;
+        let known = MPHD_FLAG_USES_GLOBAL_MAP_OBJ | MPHD_FLAG_ADT_HAS_MCCV | MPHD_FLAG_ADT_HAS_BIG_ALPHA
+            | MPHD_FLAG_ADT_HAS_DOODADS_SORTED_BY_SIZE | MPHD_FLAG_LIGHTING_VERTICES | MPHD_FLAG_UPSIDE_DOWN_GROUND
+            | MPHD_FLAG_UNK | MPHD_FLAG_ADT_HAS_HEIGHT_TEXTURING;
 
         Ok(Self {
-            has_height_texturing: has_height_texturing || unk,
+            uses_global_map_obj: i & MPHD_FLAG_USES_GLOBAL_MAP_OBJ == MPHD_FLAG_USES_GLOBAL_MAP_OBJ,
+            adt_has_mccv: i & MPHD_FLAG_ADT_HAS_MCCV == MPHD_FLAG_ADT_HAS_MCCV,
+            adt_has_big_alpha: i & MPHD_FLAG_ADT_HAS_BIG_ALPHA == MPHD_FLAG_ADT_HAS_BIG_ALPHA,
+            adt_has_doodads_sorted_by_size: i & MPHD_FLAG_ADT_HAS_DOODADS_SORTED_BY_SIZE == MPHD_FLAG_ADT_HAS_DOODADS_SORTED_BY_SIZE,
+            adt_has_lighting_vertices: i & MPHD_FLAG_LIGHTING_VERTICES == MPHD_FLAG_LIGHTING_VERTICES,
+            adt_has_upside_down_ground: i & MPHD_FLAG_UPSIDE_DOWN_GROUND == MPHD_FLAG_UPSIDE_DOWN_GROUND,
+            unk_0x40: i & MPHD_FLAG_UNK == MPHD_FLAG_UNK,
+            adt_has_height_texturing: i & MPHD_FLAG_ADT_HAS_HEIGHT_TEXTURING == MPHD_FLAG_ADT_HAS_HEIGHT_TEXTURING,
+            unknown: i & !known,
         })
     }
-} 
+}
 
-#[derive(Clone, Debug, BinRead)]
+#[derive(Clone, Debug, Default, BinRead)]
 #[br(little)]
 pub struct MPHD {
     /*
-        uint32_t version;
         uint32_t flags;
         uint32_t something;
         uint32_t unused[6];
     */
-    pub version: u32,
     pub flags: MPHDFlags,
-    pub _something: u32,
-    pub _unused: u32,
-}
\ No newline at end of file
+    pub something: u32,
+    pub unused: [u32; 6],
+}
+
+impl ChunkWrite for MPHD {
+    fn write_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&self.flags.to_bits().to_le_bytes())?;
+        writer.write_all(&self.something.to_le_bytes())?;
+        for value in self.unused {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,7 +1,55 @@
+use std::fmt;
 use std::path::PathBuf;
 
 use thiserror::Error;
 
+use crate::files::{AlphaCompression, ColorEncoding};
+
+/// Where in a file an error happened. Fields are filled in as the error is passed up
+/// through the chunk and file parsers, so any of them may be missing.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Location {
+    pub path: Option<PathBuf>,
+    /// X/Y coordinates of the ADT tile.
+    pub tile: Option<(u32, u32)>,
+    pub chunk: Option<String>,
+    /// X/Y index of the chunk within the file, for repeated chunks like MCNK.
+    pub chunk_index: Option<(u32, u32)>,
+    pub subchunk: Option<String>,
+    /// Absolute offset of the innermost (sub)chunk header in the file.
+    pub offset: Option<u64>,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts: Vec<String> = Vec::new();
+
+        if let Some(path) = &self.path {
+            parts.push(path.display().to_string());
+        }
+        if let Some((x, y)) = self.tile {
+            parts.push(format!("tile {}_{}", x, y));
+        }
+        match (&self.chunk, self.chunk_index) {
+            (Some(chunk), Some((x, y))) => parts.push(format!("{} ({}, {})", chunk, x, y)),
+            (Some(chunk), None) => parts.push(chunk.clone()),
+            _ => {},
+        }
+        if let Some(subchunk) = &self.subchunk {
+            parts.push(subchunk.clone());
+        }
+        if let Some(offset) = self.offset {
+            parts.push(format!("offset {:#x}", offset));
+        }
+
+        if parts.is_empty() {
+            write!(f, "unknown location")
+        } else {
+            write!(f, "{}", parts.join(", "))
+        }
+    }
+}
+
 /// Errors that can result from parsing the chunked files.
 #[derive(Error, Debug)]
 pub enum Error {
@@ -14,10 +62,90 @@ pub enum Error {
     /// Returned when a file does not exist.
     #[error("No file found at: {0:?}")]
     FileNotFound(PathBuf),
+    /// Returned when a chunk token is not recognised and the parser was asked to reject unknown chunks.
+    #[error("Unknown chunk {token} at offset {offset}")]
+    UnknownChunk { token: String, offset: u64 },
+    /// Returned when a chunk needed to parse a file (or its related files) is missing.
+    #[error("Missing {token} chunk in {location}")]
+    MissingChunk { location: Box<Location>, token: &'static str },
+    /// Returned when an offset stored in another chunk (e.g. MHDR or MCIN) doesn't point at the chunk it should.
+    #[error("Invalid offset to {expected} in {location}")]
+    InvalidOffset { location: Box<Location>, expected: &'static str },
+    /// Returned when a BLP uses an encoding that can't be decoded.
+    #[error("Unsupported BLP encoding: {color_encoding:?} with {alpha_compression:?}")]
+    UnsupportedBLPEncoding { color_encoding: ColorEncoding, alpha_compression: AlphaCompression },
+    /// Returned when a BLS file has an unknown shader type.
+    #[error("Invalid shader type: {0}")]
+    InvalidShaderType(String),
+    /// Returned when a chunk is smaller than its layout requires.
+    #[error("Invalid chunk size in {location}: expected at least {expected} bytes, found {actual}")]
+    InvalidSize { location: Box<Location>, expected: u64, actual: u64 },
+    /// Returned when a chunk fails to parse, with where it was found.
+    #[error("Error parsing {location}: {source}")]
+    Parse { location: Box<Location>, source: binread::Error },
     /// Wraps std::io errors.
     #[error("Error reading from file: {0}")]
     IO(#[from] std::io::Error),
     /// Wraps BinRead errors.
     #[error("Unknown parsing error: {0}")]
-    Unknown(#[from] binread::Error),
+    Unknown(binread::Error),
+}
+
+impl From<binread::Error> for Error {
+    fn from(error: binread::Error) -> Self {
+        // Errors raised inside BinRead implementations are passed back out as custom errors.
+        match error {
+            binread::Error::Custom { err, pos } => match err.downcast::<Error>() {
+                Ok(error) => *error,
+                Err(err) => Error::Unknown(binread::Error::Custom { err, pos }),
+            },
+            error => Error::Unknown(error),
+        }
+    }
+}
+
+impl Error {
+    /// Wraps the error so it can be returned from a BinRead implementation.
+    pub(crate) fn into_binread(self, pos: u64) -> binread::Error {
+        binread::Error::Custom { pos, err: Box::new(self) }
+    }
+
+    /// Where the error happened, if it is known.
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            Error::MissingChunk { location, .. }
+            | Error::InvalidOffset { location, .. }
+            | Error::InvalidSize { location, .. }
+            | Error::Parse { location, .. } => Some(location),
+            _ => None,
+        }
+    }
+
+    /// Adds to the location of a parsing error, turning unlocated BinRead errors into [`Error::Parse`].
+    pub(crate) fn locate(self, f: impl FnOnce(&mut Location)) -> Self {
+        match self {
+            Error::MissingChunk { mut location, token } => {
+                f(&mut location);
+                Error::MissingChunk { location, token }
+            },
+            Error::InvalidOffset { mut location, expected } => {
+                f(&mut location);
+                Error::InvalidOffset { location, expected }
+            },
+            Error::InvalidSize { mut location, expected, actual } => {
+                f(&mut location);
+                Error::InvalidSize { location, expected, actual }
+            },
+            Error::Parse { mut location, source } => {
+                f(&mut location);
+                Error::Parse { location, source }
+            },
+            Error::Unknown(source) => {
+                let mut location = Box::default();
+                f(&mut location);
+                Error::Parse { location, source }
+            },
+            error => error,
+        }
+    }
 }
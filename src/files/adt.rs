@@ -1,16 +1,17 @@
 use std::fs::File;
-use std::path::PathBuf;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 
-use binread::BinReaderExt;
-
-use crate::error::Error;
+use crate::error::{Error, Location};
+use crate::version::ClientVersion;
 
 use crate::files::macros;
 use crate::files::wdt;
 
 use crate::chunks;
+use crate::chunks::shared::ChunkWrite;
 
-use super::{parse_chunk_data, parse_chunk_data_args};
+use super::{handle_unknown_chunk, parse_chunk_located, write_optional_chunk, ParseOptions};
 
 #[derive(Clone, Debug, Default)]
 pub struct ADT {
@@ -18,6 +19,8 @@ pub struct ADT {
     pub path: PathBuf,
     pub x: u32,
     pub y: u32,
+    /// The client version the ADT was parsed as, either given in [`ParseOptions`] or detected.
+    pub version: ClientVersion,
 
     pub mver: Option<chunks::shared::MVER>,
     pub mhdr: Option<chunks::adt::MHDR>,
@@ -29,82 +32,992 @@ pub struct ADT {
     pub mwid: Option<chunks::adt::MWID>,
     pub mddf: Option<chunks::adt::MDDF>,
     pub modf: Option<chunks::shared::MODF>,
+    pub mh2o: Option<chunks::adt::MH2O>,
     pub mcnk: Vec<chunks::adt::MCNK>,
+    pub mfbo: Option<chunks::adt::MFBO>,
+    pub mtxf: Option<chunks::adt::MTXF>,
+
+    /// Chunks that weren't recognised, kept according to [`super::UnknownChunkPolicy`].
+    pub unknown_chunks: Vec<chunks::shared::RawChunk>,
 }
 
-fn parse_adt_file(path: PathBuf, mphd_flags: &chunks::wdt::MPHDFlags) -> Result<ADT, Error> {
-    let filename = path.file_stem().ok_or(Error::InvalidFilename(path.clone()))?
+// Figure out the coordinates of the ADT block from the filename, as it isn't available in the file itself.
+fn parse_filename(path: &Path) -> Result<(String, u32, u32), Error> {
+    let filename = path.file_stem().ok_or_else(|| Error::InvalidFilename(path.to_path_buf()))?
         .to_string_lossy().to_string();
 
-    // Figure out the coordinates of the ADT block from the filename, as it isn't available in the file itself.
-    let split: Vec<&str> = filename.split("_").collect();
-    let x: u32 = split[split.len() - 2].parse().map_err(|_| Error::MissingCoordinates(path.clone()))?;
-    let y: u32 = split[split.len() - 1].parse().map_err(|_| Error::MissingCoordinates(path.clone()))?;
+    let mut split = filename.rsplit('_').map(|v| v.parse::<u32>().ok());
+    let y: u32 = split.next().flatten().ok_or_else(|| Error::MissingCoordinates(path.to_path_buf()))?;
+    let x: u32 = split.next().flatten().ok_or_else(|| Error::MissingCoordinates(path.to_path_buf()))?;
 
-    let mut file = File::open(&path)?;
+    Ok((filename, x, y))
+}
 
-    let mut parsed_adt = ADT {
+fn parse_adt_file(path: PathBuf, mphd_flags: &chunks::wdt::MPHDFlags, options: &ParseOptions) -> Result<ADT, Error> {
+    let (filename, x, y) = parse_filename(&path)?;
+
+    let file = File::open(&path)?;
+    let parsed_adt = ADT::from_reader_with_options(file, x, y, mphd_flags, options)
+        .map_err(|e| e.locate(|location| location.path = Some(path.clone())))?;
+
+    Ok(ADT {
         filename,
         path,
+        ..parsed_adt
+    })
+}
+
+fn parse_adt<R: Read + Seek>(reader: R, x: u32, y: u32, mphd_flags: &chunks::wdt::MPHDFlags, options: &ParseOptions) -> Result<ADT, Error> {
+    let mut chunks = chunks::shared::ChunkIter::new(reader)?;
+
+    parse_adt_chunks(&mut chunks, x, y, mphd_flags, options, true)
+}
+
+// Parses the chunks of an ADT, optionally skipping over the MCNKs so they can be loaded later.
+fn parse_adt_chunks<R: Read + Seek>(
+    chunks: &mut chunks::shared::ChunkIter<R>,
+    x: u32,
+    y: u32,
+    mphd_flags: &chunks::wdt::MPHDFlags,
+    options: &ParseOptions,
+    load_mcnk: bool,
+) -> Result<ADT, Error> {
+    let mut parsed_adt = ADT {
         x,
         y,
         ..Default::default()
     };
 
-    loop {
-        if let Some(chunk_wrapper) = file.read_le::<chunks::shared::ChunkWrapper>().ok() {
-            match chunk_wrapper.token.as_str() {
-                "MVER" => macros::parse_chunk!(chunks::shared::MVER, &chunk_wrapper.data, &mut parsed_adt.mver),
-                "MHDR" => macros::parse_chunk!(chunks::adt::MHDR, &chunk_wrapper.data, &mut parsed_adt.mhdr),
-                "MCIN" => macros::parse_chunk!(chunks::adt::MCIN, &chunk_wrapper.data, &mut parsed_adt.mcin),
-                "MTEX" => macros::parse_chunk!(chunks::adt::MTEX, &chunk_wrapper.data, &mut parsed_adt.mtex),
-                "MMDX" => macros::parse_chunk!(chunks::adt::MMDX, &chunk_wrapper.data, &mut parsed_adt.mmdx),
-                "MMID" => macros::parse_chunk!(chunks::adt::MMID, &chunk_wrapper.data, &mut parsed_adt.mmid),
-                "MWMO" => macros::parse_chunk!(chunks::shared::MWMO, &chunk_wrapper.data, &mut parsed_adt.mwmo),
-                "MWID" => macros::parse_chunk!(chunks::adt::MWID, &chunk_wrapper.data, &mut parsed_adt.mwid),
-                "MDDF" => macros::parse_chunk!(chunks::adt::MDDF, &chunk_wrapper.data, &mut parsed_adt.mddf),
-                "MODF" => macros::parse_chunk!(chunks::shared::MODF, &chunk_wrapper.data, &mut parsed_adt.modf),
-                "MCNK" => {
-                    let chunk = parse_chunk_data_args::<chunks::adt::MCNK>(&chunk_wrapper.data, (mphd_flags.has_height_texturing, ))?;
-                    parsed_adt.mcnk.push(chunk);
-                },
-                _ => panic!("Unknown chunk type {}!", chunk_wrapper.token),
-            };
-        } else {
-            return Ok(parsed_adt);
+    // Only the chunk headers are needed to detect the version, so they are all read first.
+    let headers = if options.follow_offsets {
+        chunk_headers_from_offsets(chunks, options.version, load_mcnk)?
+    } else {
+        chunks.by_ref().collect::<Result<Vec<_>, _>>()?
+    };
+
+    let version = options.version.unwrap_or_else(|| {
+        let detected = ClientVersion::detect_adt(headers.iter().map(|header| header.token.as_str()));
+        detected.max(options.min_version.unwrap_or_default())
+    });
+    parsed_adt.version = version;
+
+    for header in headers {
+        match header.token.as_str() {
+            "MVER" => macros::parse_chunk!(chunks::shared::MVER, *chunks, &header, &mut parsed_adt.mver),
+            "MHDR" => macros::parse_chunk!(chunks::adt::MHDR, *chunks, &header, &mut parsed_adt.mhdr),
+            "MCIN" => macros::parse_chunk!(chunks::adt::MCIN, *chunks, &header, &mut parsed_adt.mcin),
+            "MTEX" => macros::parse_chunk!(chunks::adt::MTEX, *chunks, &header, &mut parsed_adt.mtex),
+            "MMDX" => macros::parse_chunk!(chunks::adt::MMDX, *chunks, &header, &mut parsed_adt.mmdx),
+            "MMID" => macros::parse_chunk!(chunks::adt::MMID, *chunks, &header, &mut parsed_adt.mmid),
+            "MWMO" => macros::parse_chunk!(chunks::shared::MWMO, *chunks, &header, &mut parsed_adt.mwmo),
+            "MWID" => macros::parse_chunk!(chunks::adt::MWID, *chunks, &header, &mut parsed_adt.mwid),
+            "MDDF" => macros::parse_chunk!(chunks::adt::MDDF, *chunks, &header, &mut parsed_adt.mddf),
+            "MODF" => macros::parse_chunk!(chunks::shared::MODF, *chunks, &header, &mut parsed_adt.modf),
+            "MH2O" => parsed_adt.mh2o = Some(parse_chunk_located(chunks, &header, (options.liquid_vertex_format,))?),
+            "MFBO" => macros::parse_chunk!(chunks::adt::MFBO, *chunks, &header, &mut parsed_adt.mfbo),
+            "MTXF" => macros::parse_chunk!(chunks::adt::MTXF, *chunks, &header, &mut parsed_adt.mtxf),
+            "MCNK" if !load_mcnk => {},
+            "MCNK" => {
+                let index = parsed_adt.mcnk.len() as u32;
+                let chunk = parse_mcnk(chunks, &header, (index % 16, index / 16), mphd_flags, version)?;
+                parsed_adt.mcnk.push(chunk);
+            },
+            _ => handle_unknown_chunk(options, chunks, header, &mut parsed_adt.unknown_chunks)?,
+        };
+    }
+
+    Ok(parsed_adt)
+}
+
+// Finds the chunks through the MHDR and MCIN offsets, instead of walking the file in order.
+//
+// Without an explicit version, every offset is followed, as the ones a version doesn't have are normally 0.
+fn chunk_headers_from_offsets<R: Read + Seek>(
+    chunks: &mut chunks::shared::ChunkIter<R>,
+    version: Option<ClientVersion>,
+    include_mcnk: bool,
+) -> Result<Vec<chunks::shared::ChunkHeader>, Error> {
+    // MHDR comes right after MVER, at the start of the file.
+    let mut headers = Vec::new();
+    let mhdr_header = loop {
+        match chunks.next().transpose()? {
+            Some(header) if header.token == "MHDR" => break header,
+            Some(header) if header.token == "MVER" => headers.push(header),
+            _ => return Err(Error::MissingChunk { location: Box::default(), token: "MHDR" }),
+        }
+    };
+    let start = headers.first().unwrap_or(&mhdr_header).offset;
+
+    let mhdr: chunks::adt::MHDR = parse_chunk_located(chunks, &mhdr_header, ())?;
+    headers.push(mhdr_header.clone());
+
+    for token in chunks::adt::MHDR::tokens(version.unwrap_or(ClientVersion::WotLK)) {
+        if let Some(offset) = mhdr.offset(token) {
+            headers.push(header_at(chunks, mhdr_header.data_offset() + u64::from(offset), token)?);
+        }
+    }
+
+    if include_mcnk {
+        if let Some(mcin_header) = headers.iter().find(|header| header.token == "MCIN") {
+            let mcin: chunks::adt::MCIN = parse_chunk_located(chunks, mcin_header, ())?;
+            for entry in mcin.chunks.iter().filter(|entry| entry.offset != 0) {
+                headers.push(header_at(chunks, start + u64::from(entry.offset), "MCNK")?);
+            }
         }
     }
+
+    Ok(headers)
+}
+
+// Reads the header of the chunk at an offset, checking that it is the expected chunk.
+fn header_at<R: Read + Seek>(chunks: &mut chunks::shared::ChunkIter<R>, offset: u64, token: &'static str) -> Result<chunks::shared::ChunkHeader, Error> {
+    let invalid = || Error::InvalidOffset {
+        location: Box::new(Location { offset: Some(offset), ..Default::default() }),
+        expected: token,
+    };
+
+    match chunks.header_at(offset) {
+        Ok(header) if header.token == token => Ok(header),
+        _ => Err(invalid()),
+    }
+}
+
+fn parse_mcnk<R: Read + Seek>(
+    chunks: &mut chunks::shared::ChunkIter<R>,
+    header: &chunks::shared::ChunkHeader,
+    (x, y): (u32, u32),
+    mphd_flags: &chunks::wdt::MPHDFlags,
+    version: ClientVersion,
+) -> Result<chunks::adt::MCNK, Error> {
+    parse_chunk_located::<_, chunks::adt::MCNK>(chunks, header, (*mphd_flags, version))
+        .map_err(|e| e.locate(|location| location.chunk_index = Some((x, y))))
 }
 
 impl ADT {
     pub fn from_file(path: PathBuf, mphd_flags: &chunks::wdt::MPHDFlags) -> Result<Self, Error> {
+        Self::from_file_with_options(path, mphd_flags, &ParseOptions::default())
+    }
+
+    pub fn from_file_with_options(path: PathBuf, mphd_flags: &chunks::wdt::MPHDFlags, options: &ParseOptions) -> Result<Self, Error> {
         if !path.exists() {
             return Err(Error::FileNotFound(path))
         }
 
-        Ok(parse_adt_file(path, mphd_flags)?) 
+        parse_adt_file(path, mphd_flags, options)
     }
 
-    pub fn from_wdt_file(wdt_filename: PathBuf, x: u32, y: u32) -> Result<Self, Error> {
-        let adt_name = format!("{}_{}_{}.adt", &wdt_filename.file_stem().and_then(|n| n.to_str()).expect("WDT should have a extension."), x, y);
-        let adt_path = wdt_filename
-            .parent().expect("WDT file should be in a folder with the ADT files.")
-            .join(adt_name);
+    /// Parses an ADT from any seekable source, such as a file extracted from an archive.
+    ///
+    /// As the tile coordinates are normally taken from the filename, they have to be passed explicitly.
+    pub fn from_reader<R: Read + Seek>(reader: R, x: u32, y: u32, mphd_flags: &chunks::wdt::MPHDFlags) -> Result<Self, Error> {
+        Self::from_reader_with_options(reader, x, y, mphd_flags, &ParseOptions::default())
+    }
+
+    pub fn from_reader_with_options<R: Read + Seek>(reader: R, x: u32, y: u32, mphd_flags: &chunks::wdt::MPHDFlags, options: &ParseOptions) -> Result<Self, Error> {
+        parse_adt(reader, x, y, mphd_flags, options)
+            .map_err(|e| e.locate(|location| location.tile = Some((x, y))))
+    }
 
+    /// Parses an ADT from an in-memory buffer.
+    pub fn from_bytes(bytes: &[u8], x: u32, y: u32, mphd_flags: &chunks::wdt::MPHDFlags) -> Result<Self, Error> {
+        Self::from_reader(Cursor::new(bytes), x, y, mphd_flags)
+    }
+
+    pub fn from_wdt_file(wdt_filename: PathBuf, x: u32, y: u32) -> Result<Self, Error> {
         let wdt = wdt::WDT::from_file(wdt_filename)?;
-        let adt = ADT::from_file(adt_path, &wdt.mphd.and_then(|chunk| Some(chunk.flags)).expect("WDT should have a valid MPHD chunk"))?;
 
-        Ok(adt)
+        ADT::from_wdt(&wdt, x, y)
     }
-    
+
     pub fn from_wdt(wdt: &wdt::WDT, x: u32, y: u32) -> Result<Self, Error> {
-        let adt_name = format!("{}_{}_{}.adt", wdt.path.file_stem().and_then(|n| n.to_str()).expect("WDT should have a extension."), x, y);
+        // ADTs are stored next to their WDT, named after it.
+        let wdt_name = wdt.path.file_stem()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| Error::InvalidFilename(wdt.path.clone()))?;
         let adt_path = wdt.path
-            .parent().expect("WDT file should be in a folder with the ADT files.")
-            .join(adt_name);
+            .parent()
+            .ok_or_else(|| Error::InvalidFilename(wdt.path.clone()))?
+            .join(format!("{}_{}_{}.adt", wdt_name, x, y));
+
+        let mphd = wdt.mphd.as_ref()
+            .ok_or_else(|| Error::MissingChunk {
+                location: Box::new(Location { path: Some(wdt.path.clone()), ..Default::default() }),
+                token: "MPHD",
+            })?;
+
+        // The WDT's version is only a lower bound if it was detected, so the ADT's own chunks can raise it.
+        let options = ParseOptions { min_version: Some(wdt.version), ..Default::default() };
+        ADT::from_file_with_options(adt_path, &mphd.flags, &options)
+    }
+
+    /// The MH2O liquids of the MCNK at an index (`y * 16 + x`), for 3.x ADTs with any liquid in that chunk.
+    pub fn mh2o_chunk(&self, index: usize) -> Option<&chunks::adt::MH2OChunk> {
+        self.mh2o.as_ref().and_then(|mh2o| mh2o.chunk(index))
+    }
+
+    /// The liquids of an MCNK, from MH2O if the ADT has one, or otherwise from the MCNK's MCLQ.
+    pub fn chunk_liquids(&self, chunk: &chunks::adt::MCNK) -> Vec<chunks::adt::Liquid> {
+        match &self.mh2o {
+            Some(mh2o) => mh2o.chunks.get((chunk.y * 16 + chunk.x) as usize)
+                .map_or_else(Vec::new, chunks::adt::MH2OChunk::liquids),
+            None => chunk.mclq.liquids(),
+        }
+    }
+
+    /// The MTEX textures, each with its MTXF flags.
+    pub fn textures(&self) -> Vec<chunks::adt::Texture<'_>> {
+        let filenames = self.mtex.as_ref().map_or(&[][..], |mtex| &mtex.filenames);
+        let flags = self.mtxf.as_ref().map_or(&[][..], |mtxf| &mtxf.flags);
+
+        filenames.iter().enumerate()
+            .map(|(i, filename)| chunks::adt::Texture {
+                filename,
+                flags: flags.get(i).copied().unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    /// The doodads referenced by an MCNK's MCRF, resolved to their MDDF placements.
+    ///
+    /// References to missing MDDF entries are skipped.
+    pub fn chunk_doodads(&self, chunk: &chunks::adt::MCNK) -> Vec<DoodadPlacement<'_>> {
+        let Some(mddf) = &self.mddf else { return Vec::new() };
+
+        chunk.mcrf.doodad_refs().iter()
+            .filter_map(|index| mddf.parts.get(*index as usize))
+            .map(|placement| DoodadPlacement {
+                placement,
+                filename: self.doodad_filename(placement.name_id),
+            })
+            .collect()
+    }
+
+    /// The WMOs referenced by an MCNK's MCRF, resolved to their MODF placements.
+    ///
+    /// References to missing MODF entries are skipped.
+    pub fn chunk_wmos(&self, chunk: &chunks::adt::MCNK) -> Vec<WMOPlacement<'_>> {
+        let Some(modf) = &self.modf else { return Vec::new() };
+
+        chunk.mcrf.map_obj_refs().iter()
+            .filter_map(|index| modf.parts.get(*index as usize))
+            .map(|placement| WMOPlacement {
+                placement,
+                filename: self.wmo_filename(placement.name_id),
+            })
+            .collect()
+    }
+
+    /// The filename of a doodad model, from the `name_id` of its MDDF entry.
+    pub fn doodad_filename(&self, name_id: u32) -> Option<&str> {
+        let offset = self.mmid.as_ref()?.offsets.get(name_id as usize)?;
+        self.mmdx.as_ref()?.filename_at(*offset)
+    }
+
+    /// The filename of a WMO, from the `name_id` of its MODF entry.
+    pub fn wmo_filename(&self, name_id: u32) -> Option<&str> {
+        let offset = self.mwid.as_ref()?.offsets.get(name_id as usize)?;
+        self.mwmo.as_ref()?.filename_at(*offset)
+    }
+
+    /// Position within the tile as fractions from its north-west corner (column, row),
+    /// or `None` if the world position is outside of the tile.
+    fn tile_position(&self, x: f32, y: f32) -> Option<(f32, f32)> {
+        let (corner_x, corner_y) = chunks::shared::tile_corner(self.x, self.y);
+        let column = (corner_y - y) / chunks::shared::ADT_SIZE;
+        let row = (corner_x - x) / chunks::shared::ADT_SIZE;
+
+        // Allow for rounding, so positions on the edge between two tiles are in both.
+        let range = -1e-5..=1.0 + 1e-5;
+        (range.contains(&column) && range.contains(&row)).then_some((column.clamp(0.0, 1.0), row.clamp(0.0, 1.0)))
+    }
+
+    /// The flight ceiling at a world position, for tiles with a MFBO chunk.
+    pub fn flight_ceiling_at(&self, x: f32, y: f32) -> Option<f32> {
+        let (column, row) = self.tile_position(x, y)?;
+        self.mfbo.as_ref().map(|mfbo| mfbo.ceiling_at(column, row))
+    }
+
+    /// The flight floor at a world position, for tiles with a MFBO chunk.
+    pub fn flight_floor_at(&self, x: f32, y: f32) -> Option<f32> {
+        let (column, row) = self.tile_position(x, y)?;
+        self.mfbo.as_ref().map(|mfbo| mfbo.floor_at(column, row))
+    }
+
+    /// The terrain height at a world position, or `None` if it is outside of the tile or in a hole.
+    ///
+    /// See [`chunks::adt::MCNK::height_at`]. Positions on the edge between two MCNKs are only in a
+    /// hole if they are in a hole in both.
+    pub fn height_at(&self, x: f32, y: f32) -> Option<f32> {
+        self.mcnk.iter().find_map(|chunk| chunk.height_at(x, y))
+    }
+
+    /// Serialises the ADT, recomputing the MHDR offsets, the MCIN entries and the MCNK subchunk offsets.
+    ///
+    /// The MH2O chunk and the MCAL, MCSH, MCLQ and MCSE subchunks are written back exactly
+    /// as they were read, so changes to their parsed data aren't saved. Any bytes between or after
+    /// the subchunks of an MCNK are kept in front of the same subchunks.
+    pub fn write_to<W: Write + Seek>(&self, writer: &mut W) -> Result<(), Error> {
+        let start = writer.stream_position()?;
+
+        write_optional_chunk(writer, "MVER", &self.mver)?;
+
+        // MHDR and MCIN point at chunks that haven't been written yet, so are written
+        // as placeholders first and filled in at the end.
+        let mhdr_offset = write_optional_chunk(writer, "MHDR", &self.mhdr)?;
+        let mcin_offset = write_optional_chunk(writer, "MCIN", &self.mcin)?;
+
+        let mtex_offset = write_optional_chunk(writer, "MTEX", &self.mtex)?;
+        let mmdx_offset = write_optional_chunk(writer, "MMDX", &self.mmdx)?;
+        let mmid_offset = write_optional_chunk(writer, "MMID", &self.mmid)?;
+        let mwmo_offset = write_optional_chunk(writer, "MWMO", &self.mwmo)?;
+        let mwid_offset = write_optional_chunk(writer, "MWID", &self.mwid)?;
+        let mddf_offset = write_optional_chunk(writer, "MDDF", &self.mddf)?;
+        let modf_offset = write_optional_chunk(writer, "MODF", &self.modf)?;
+        let mh2o_offset = write_optional_chunk(writer, "MH2O", &self.mh2o)?;
+
+        // Unknown chunks that came before the MCNKs originally are kept before them.
+        let first_mcnk = self.mcin.as_ref()
+            .and_then(|mcin| mcin.chunks.first())
+            .map(|chunk| u64::from(chunk.offset));
+        let (before, after): (Vec<_>, Vec<_>) = self.unknown_chunks.iter()
+            .partition(|chunk| first_mcnk.is_some_and(|offset| chunk.offset < offset));
+
+        for chunk in before {
+            chunks::shared::write_chunk(writer, &chunk.token, &chunk.data)?;
+        }
+
+        let mut mcnk_entries: Vec<(u32, u32)> = Vec::with_capacity(self.mcnk.len());
+        for mcnk in self.mcnk.iter() {
+            let data = mcnk.to_bytes()?;
+            let offset = chunks::shared::write_chunk(writer, "MCNK", &data)?;
+            mcnk_entries.push(((offset - start) as u32, data.len() as u32 + 8));
+        }
+
+        let mfbo_offset = write_optional_chunk(writer, "MFBO", &self.mfbo)?;
+        let mtxf_offset = write_optional_chunk(writer, "MTXF", &self.mtxf)?;
+
+        for chunk in after {
+            chunks::shared::write_chunk(writer, &chunk.token, &chunk.data)?;
+        }
+
+        let end = writer.stream_position()?;
+
+        if let (Some(mcin), Some(offset)) = (&self.mcin, mcin_offset) {
+            let mut mcin = mcin.clone();
+            for (entry, (offset, size)) in mcin.chunks.iter_mut().zip(mcnk_entries) {
+                entry.offset = offset;
+                entry.size = size;
+            }
+
+            writer.seek(SeekFrom::Start(offset))?;
+            chunks::shared::write_chunk(writer, "MCIN", &mcin.to_bytes()?)?;
+        }
+
+        if let (Some(mhdr), Some(offset)) = (&self.mhdr, mhdr_offset) {
+            // MHDR offsets are relative to the start of its own data.
+            let data_offset = offset + chunks::shared::ChunkHeader::SIZE;
+            let relative = |offset: Option<u64>| offset.map(|offset| (offset - data_offset) as u32);
+
+            let mut mhdr = mhdr.clone();
+            for (token, offset) in [
+                ("MCIN", mcin_offset),
+                ("MTEX", mtex_offset),
+                ("MMDX", mmdx_offset),
+                ("MMID", mmid_offset),
+                ("MWMO", mwmo_offset),
+                ("MWID", mwid_offset),
+                ("MDDF", mddf_offset),
+                ("MODF", modf_offset),
+                ("MFBO", mfbo_offset),
+                ("MH2O", mh2o_offset),
+                ("MTXF", mtxf_offset),
+            ] {
+                mhdr.set_offset(token, relative(offset));
+            }
+
+            writer.seek(SeekFrom::Start(offset))?;
+            chunks::shared::write_chunk(writer, "MHDR", &mhdr.to_bytes()?)?;
+        }
+
+        writer.seek(SeekFrom::Start(end))?;
+
+        Ok(())
+    }
+
+    /// Serialises the ADT into a new buffer, see [`ADT::write_to`].
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut cursor = Cursor::new(Vec::new());
+        self.write_to(&mut cursor)?;
+
+        Ok(cursor.into_inner())
+    }
+}
+
+/// A doodad placed in an MCNK, with the filename of its model.
+#[derive(Clone, Copy, Debug)]
+pub struct DoodadPlacement<'a> {
+    pub placement: &'a chunks::adt::MDDFPart,
+    /// The M2 model, if MMID and MMDX have it.
+    pub filename: Option<&'a str>,
+}
+
+/// A WMO placed in an MCNK, with the filename of its model.
+#[derive(Clone, Copy, Debug)]
+pub struct WMOPlacement<'a> {
+    pub placement: &'a chunks::shared::MODFPart,
+    /// The WMO model, if MWID and MWMO have it.
+    pub filename: Option<&'a str>,
+}
+
+/// An ADT whose MCNKs are only decoded when they are first requested.
+///
+/// The other chunks are parsed up front into [`LazyADT::adt`], which is left without any MCNKs.
+/// Each MCNK is then read straight from its MCIN offset, so only the chunks that are actually
+/// used are decoded.
+pub struct LazyADT<R> {
+    pub adt: ADT,
+
+    chunks: chunks::shared::ChunkIter<R>,
+    // Offset of the start of the ADT in the reader, which the MCIN offsets are relative to.
+    start: u64,
+    mphd_flags: chunks::wdt::MPHDFlags,
+    mcnk: Vec<Option<chunks::adt::MCNK>>,
+}
+
+impl LazyADT<File> {
+    pub fn from_file(path: PathBuf, mphd_flags: &chunks::wdt::MPHDFlags) -> Result<Self, Error> {
+        Self::from_file_with_options(path, mphd_flags, &ParseOptions::default())
+    }
+
+    pub fn from_file_with_options(path: PathBuf, mphd_flags: &chunks::wdt::MPHDFlags, options: &ParseOptions) -> Result<Self, Error> {
+        if !path.exists() {
+            return Err(Error::FileNotFound(path))
+        }
+
+        let (filename, x, y) = parse_filename(&path)?;
+
+        let file = File::open(&path)?;
+        let mut lazy_adt = Self::from_reader_with_options(file, x, y, mphd_flags, options)
+            .map_err(|e| e.locate(|location| location.path = Some(path.clone())))?;
+        lazy_adt.adt.filename = filename;
+        lazy_adt.adt.path = path;
+
+        Ok(lazy_adt)
+    }
+}
+
+impl<R: Read + Seek> LazyADT<R> {
+    pub fn from_reader(reader: R, x: u32, y: u32, mphd_flags: &chunks::wdt::MPHDFlags) -> Result<Self, Error> {
+        Self::from_reader_with_options(reader, x, y, mphd_flags, &ParseOptions::default())
+    }
+
+    pub fn from_reader_with_options(mut reader: R, x: u32, y: u32, mphd_flags: &chunks::wdt::MPHDFlags, options: &ParseOptions) -> Result<Self, Error> {
+        let start = reader.stream_position()?;
+        let mut chunks = chunks::shared::ChunkIter::new(reader)?;
+
+        let adt = parse_adt_chunks(&mut chunks, x, y, mphd_flags, options, false)
+            .map_err(|e| e.locate(|location| location.tile = Some((x, y))))?;
+
+        Ok(Self {
+            adt,
+            chunks,
+            start,
+            mphd_flags: *mphd_flags,
+            mcnk: vec![None; 16 * 16],
+        })
+    }
+
+    /// The MCNK at (x, y) in the 16x16 grid of the ADT, decoding it if it hasn't been already.
+    ///
+    /// Returns `None` if the coordinates are out of range or MCIN has no entry for the chunk.
+    pub fn chunk(&mut self, x: u32, y: u32) -> Result<Option<&chunks::adt::MCNK>, Error> {
+        let mcin = self.adt.mcin.as_ref().ok_or_else(|| Error::MissingChunk {
+            location: Box::new(Location {
+                path: Some(self.adt.path.clone()).filter(|path| !path.as_os_str().is_empty()),
+                tile: Some((self.adt.x, self.adt.y)),
+                ..Default::default()
+            }),
+            token: "MCIN",
+        })?;
+        let entry = match mcin.entry(x, y) {
+            Some(entry) if entry.offset != 0 => entry,
+            _ => return Ok(None),
+        };
+        let index = (y * 16 + x) as usize;
+
+        if self.mcnk[index].is_none() {
+            let tile = (self.adt.x, self.adt.y);
+            let header = header_at(&mut self.chunks, self.start + u64::from(entry.offset), "MCNK")
+                .map_err(|e| e.locate(|location| location.tile = Some(tile)))?;
+
+            let chunk = parse_mcnk(&mut self.chunks, &header, (x, y), &self.mphd_flags, self.adt.version)
+                .map_err(|e| e.locate(|location| location.tile = Some(tile)))?;
+            self.mcnk[index] = Some(chunk);
+        }
+
+        Ok(self.mcnk[index].as_ref())
+    }
+
+    /// Decodes all remaining MCNKs, returning the fully loaded ADT.
+    pub fn into_adt(mut self) -> Result<ADT, Error> {
+        let mut mcnk = Vec::with_capacity(16 * 16);
+        for index in 0..16 * 16 {
+            if self.chunk(index % 16, index / 16)?.is_some() {
+                mcnk.extend(self.mcnk[index as usize].take());
+            }
+        }
+
+        Ok(ADT {
+            mcnk,
+            ..self.adt
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::files::fixtures::{push_chunk, push_chunk_with_size, push_u32s};
+    use crate::files::UnknownChunkPolicy;
+
+    fn raw_mcnk() -> Vec<u8> {
+        raw_mcnk_with_gaps(&[])
+    }
+
+    // An MCNK with `gap` after its MCLY and at the end, like files padded by other tools.
+    fn raw_mcnk_with_gaps(gap: &[u8]) -> Vec<u8> {
+        let mut subchunks: Vec<u8> = Vec::new();
+
+        let heights: Vec<u8> = (0..145).flat_map(|i| (i as f32 * 0.37 - 12.5).to_le_bytes()).collect();
+        let ofs_height = push_chunk(&mut subchunks, "MCVT", &heights);
+
+        let mut normals: Vec<u8> = (0..145 * 3).map(|i| (i * 7 % 256) as u8).collect();
+        normals.extend([0xAB; 13]);
+        let ofs_normal = push_chunk_with_size(&mut subchunks, "MCNR", 435, &normals);
+
+        let mut layer = Vec::new();
+        push_u32s(&mut layer, &[0, 0x1, 0, 0]);
+        let ofs_layer = push_chunk(&mut subchunks, "MCLY", &layer);
+        subchunks.extend(gap);
+        let ofs_refs = push_chunk(&mut subchunks, "MCRF", &[]);
+        let ofs_alpha = push_chunk(&mut subchunks, "MCAL", &[]);
+        let ofs_liquid = push_chunk(&mut subchunks, "MCLQ", &[]);
+        let ofs_snd_emitters = push_chunk(&mut subchunks, "MCSE", &[]);
+        let colors: Vec<u8> = (0..145 * 4).map(|i| (i % 255) as u8).collect();
+        let ofs_mccv = push_chunk(&mut subchunks, "MCCV", &colors);
+        subchunks.extend(gap);
+
+        let ofs = |o: usize| (o + 136) as u32;
+
+        let mut data = Vec::new();
+        push_u32s(&mut data, &[
+            0x8040, 3, 5, 1, 0,
+            ofs(ofs_height), ofs(ofs_normal), ofs(ofs_layer), ofs(ofs_refs), ofs(ofs_alpha), 8, 0, 0,
+            12, 0,
+        ]);
+        data.extend([0x02, 0x01, 0x00, 0x00]);
+        data.extend([0x11; 16]);
+        data.extend([0x22; 8]);
+        push_u32s(&mut data, &[ofs(ofs_snd_emitters), 0, ofs(ofs_liquid), 8]);
+        for v in [1000.5f32, 2000.25, 50.0] {
+            data.extend(v.to_le_bytes());
+        }
+        push_u32s(&mut data, &[ofs(ofs_mccv), 0, 0]);
+        assert_eq!(data.len(), 128);
+
+        data.extend(subchunks);
+        data
+    }
+
+    fn raw_adt() -> Vec<u8> {
+        raw_adt_with_mcnk(raw_mcnk())
+    }
+
+    fn raw_adt_with_mcnk(mcnk_data: Vec<u8>) -> Vec<u8> {
+        let mut buf = Vec::new();
+
+        push_chunk(&mut buf, "MVER", &18u32.to_le_bytes());
+        let mhdr = push_chunk(&mut buf, "MHDR", &[0; 64]);
+        let mcin = push_chunk(&mut buf, "MCIN", &[0; 4096]);
+        let mut offsets = vec![mcin];
+        offsets.push(push_chunk(&mut buf, "MTEX", b"tex.blp\0"));
+        offsets.push(push_chunk(&mut buf, "MMDX", &[]));
+        offsets.push(push_chunk(&mut buf, "MMID", &[]));
+        offsets.push(push_chunk(&mut buf, "MWMO", &[]));
+        offsets.push(push_chunk(&mut buf, "MWID", &[]));
+        offsets.push(push_chunk(&mut buf, "MDDF", &[]));
+        offsets.push(push_chunk(&mut buf, "MODF", &[]));
+
+        let mcnk = push_chunk(&mut buf, "MCNK", &mcnk_data);
+
+        // Flags, followed by the offsets relative to the MHDR data.
+        let mut mhdr_data = vec![0; 4];
+        push_u32s(&mut mhdr_data, &offsets.iter().map(|o| (o - mhdr - 8) as u32).collect::<Vec<u32>>());
+        buf[mhdr + 8..mhdr + 8 + mhdr_data.len()].copy_from_slice(&mhdr_data);
+
+        let mut mcin_entry = Vec::new();
+        push_u32s(&mut mcin_entry, &[mcnk as u32, mcnk_data.len() as u32 + 8]);
+        buf[mcin + 8..mcin + 16].copy_from_slice(&mcin_entry);
+
+        buf
+    }
+
+    #[test]
+    fn write_round_trip() {
+        let raw = raw_adt();
+        let adt = ADT::from_bytes(&raw, 1, 2, &chunks::wdt::MPHDFlags::default()).unwrap();
+
+        assert_eq!(adt.mcnk.len(), 1);
+        assert_eq!(adt.mcnk[0].flags.to_bits(), 0x8040);
+        assert!(adt.mcnk[0].mccv.is_none());
+        assert_eq!(adt.to_bytes().unwrap(), raw);
+    }
+
+    #[test]
+    fn write_gaps() {
+        let raw = raw_adt_with_mcnk(raw_mcnk_with_gaps(&[0xEE; 12]));
+        let mphd_flags = chunks::wdt::MPHDFlags { adt_has_mccv: true, ..Default::default() };
+        let options = ParseOptions { version: Some(ClientVersion::WotLK), ..Default::default() };
+        let mut adt = ADT::from_reader_with_options(Cursor::new(&raw), 1, 2, &mphd_flags, &options).unwrap();
+
+        assert_eq!(adt.mcnk[0].mcly.layers.len(), 1);
+        assert!(adt.mcnk[0].mccv.is_some());
+        assert_eq!(adt.to_bytes().unwrap(), raw);
+
+        // The gaps stay in front of the same subchunks after the ones before them change size.
+        let layer = adt.mcnk[0].mcly.layers[0].clone();
+        adt.mcnk[0].mcly.layers.push(layer);
+        let written = adt.to_bytes().unwrap();
+        assert_eq!(written.len(), raw.len() + 16);
+        let adt = ADT::from_reader_with_options(Cursor::new(&written), 1, 2, &mphd_flags, &options).unwrap();
+        assert_eq!(adt.mcnk[0].mcly.layers.len(), 2);
+        assert_eq!(adt.mcnk[0].mccv.as_ref().unwrap().colors.len(), 145);
+    }
+
+    #[test]
+    fn unknown_chunk_policies() {
+        let mut raw = raw_adt();
+        let offset = push_chunk(&mut raw, "MXYZ", &[1, 2, 3, 4]);
+        let parse = |unknown_chunks| {
+            let options = ParseOptions { unknown_chunks, ..Default::default() };
+            ADT::from_reader_with_options(Cursor::new(&raw), 1, 2, &chunks::wdt::MPHDFlags::default(), &options)
+        };
+
+        let adt = parse(UnknownChunkPolicy::Keep).unwrap();
+        assert_eq!(adt.unknown_chunks.len(), 1);
+        assert_eq!(adt.unknown_chunks[0].token, "MXYZ");
+        assert_eq!(adt.unknown_chunks[0].offset, offset as u64);
+        assert_eq!(adt.unknown_chunks[0].data, vec![1, 2, 3, 4]);
+        assert_eq!(adt.to_bytes().unwrap(), raw);
+
+        let adt = parse(UnknownChunkPolicy::Ignore).unwrap();
+        assert!(adt.unknown_chunks.is_empty());
+        assert_eq!(adt.mcnk.len(), 1);
+
+        let error = parse(UnknownChunkPolicy::Error).unwrap_err();
+        assert!(matches!(error, Error::UnknownChunk { ref token, offset: o } if token == "MXYZ" && o == offset as u64));
+    }
+
+    #[test]
+    fn parse_mccv() {
+        let raw = raw_adt();
+        let mphd_flags = chunks::wdt::MPHDFlags { adt_has_mccv: true, ..Default::default() };
+        let parse = |version| {
+            let options = ParseOptions { version: Some(version), ..Default::default() };
+            ADT::from_reader_with_options(Cursor::new(&raw), 1, 2, &mphd_flags, &options).unwrap()
+        };
+
+        let adt = parse(ClientVersion::WotLK);
+        let mccv = adt.mcnk[0].mccv.as_ref().unwrap();
+        assert_eq!(mccv.colors.len(), 145);
+        assert_eq!(mccv.colors[1], chunks::shared::CImVector { b: 4, g: 5, r: 6, a: 7 });
+        assert_eq!(adt.to_bytes().unwrap(), raw);
+
+        // MCCV only exists from 3.x, so the same flags are ignored for older versions.
+        let adt = parse(ClientVersion::TBC);
+        assert!(adt.mcnk[0].mccv.is_none());
+        assert_eq!(adt.to_bytes().unwrap(), raw);
+    }
+
+    #[test]
+    fn minimum_version() {
+        let raw = raw_adt();
+        let parse = |version, min_version| {
+            let options = ParseOptions { version, min_version, ..Default::default() };
+            ADT::from_reader_with_options(Cursor::new(&raw), 1, 2, &chunks::wdt::MPHDFlags::default(), &options).unwrap().version
+        };
+
+        // Nothing in the fixture is newer than 1.12, so only the WDT's version can raise it.
+        assert_eq!(parse(None, None), ClientVersion::Vanilla);
+        assert_eq!(parse(None, Some(ClientVersion::TBC)), ClientVersion::TBC);
+        assert_eq!(parse(Some(ClientVersion::Vanilla), Some(ClientVersion::WotLK)), ClientVersion::Vanilla);
+
+        let mut raw = raw.clone();
+        push_chunk(&mut raw, "MTXF", &[]);
+        let options = ParseOptions { min_version: Some(ClientVersion::TBC), ..Default::default() };
+        let adt = ADT::from_reader_with_options(Cursor::new(&raw), 1, 2, &chunks::wdt::MPHDFlags::default(), &options).unwrap();
+        assert_eq!(adt.version, ClientVersion::WotLK);
+    }
+
+    #[test]
+    fn lazy_chunk() {
+        let raw = raw_adt();
+        let mut adt = LazyADT::from_reader(Cursor::new(&raw), 1, 2, &chunks::wdt::MPHDFlags::default()).unwrap();
+
+        assert!(adt.adt.mcnk.is_empty());
+        assert!(adt.chunk(1, 0).unwrap().is_none());
+        assert_eq!(adt.chunk(0, 0).unwrap().unwrap().flags.to_bits(), 0x8040);
+
+        let adt = adt.into_adt().unwrap();
+        assert_eq!(adt.mcnk.len(), 1);
+        assert_eq!(adt.to_bytes().unwrap(), raw);
+    }
+
+    #[test]
+    fn chunk_holes() {
+        let adt = ADT::from_bytes(&raw_adt(), 1, 2, &chunks::wdt::MPHDFlags::default()).unwrap();
+        let chunk = &adt.mcnk[0];
+        let quad = chunks::shared::CHUNK_SIZE / 8.0;
+
+        assert_eq!(chunk.holes_low_res, 0x0102);
+        assert!(chunk.is_hole(1000.5 - 1.0, 2000.25 - 2.5 * quad));
+        assert!(chunk.is_hole(1000.5 - 4.5 * quad, 2000.25 - 0.5 * quad));
+        assert!(!chunk.is_hole(1000.5 - 0.5, 2000.25 - 0.5));
+        assert!(!chunk.is_hole(1001.0, 2000.25 - 2.5 * quad));
+    }
+
+    #[test]
+    fn flight_bounds() {
+        let mut adt = ADT::from_bytes(&raw_adt(), 1, 2, &chunks::wdt::MPHDFlags::default()).unwrap();
+        let (north, west) = chunks::shared::tile_corner(1, 2);
+        let size = chunks::shared::ADT_SIZE;
+        let close = |a: Option<f32>, b: f32| a.is_some_and(|a| (a - b).abs() < 1e-2);
+        assert_eq!(chunks::shared::tile_at(north - size / 2.0, west - size / 2.0), Some((1, 2)));
+        assert_eq!(adt.flight_ceiling_at(north, west), None);
+
+        adt.mfbo = Some(chunks::adt::MFBO {
+            maximum: chunks::adt::MFBOPlane { heights: [[100, 200, 300], [400, 500, 600], [700, 800, 900]] },
+            minimum: chunks::adt::MFBOPlane { heights: [[-10, -20, -30], [-40, -50, -60], [-70, -80, -90]] },
+        });
+
+        // Tile x runs west to east along world y, and tile y north to south along world x.
+        assert!(close(adt.flight_ceiling_at(north, west), 100.0));
+        assert!(close(adt.flight_ceiling_at(north, west - size), 300.0));
+        assert!(close(adt.flight_ceiling_at(north - size, west), 700.0));
+        assert!(close(adt.flight_ceiling_at(north - size, west - size), 900.0));
+        assert!(close(adt.flight_floor_at(north - size, west - size), -90.0));
+        assert!(close(adt.flight_floor_at(north - size / 2.0, west - size / 4.0), -45.0));
+
+        assert_eq!(adt.flight_ceiling_at(north + 1.0, west), None);
+        assert_eq!(adt.flight_floor_at(north - size / 2.0, west - size - 1.0), None);
+    }
+
+    #[test]
+    fn high_res_holes() {
+        let mut raw = raw_adt();
+        let mcnk = raw.windows(4).position(|token| token == b"KNCM").unwrap() + 8;
+        let mut header = Vec::new();
+        push_u32s(&mut header, &[0x18040, 3, 5, 1, 0, 0x0000_0002, 0x0100_0000]);
+        raw[mcnk..mcnk + 28].copy_from_slice(&header);
+
+        let adt = ADT::from_bytes(&raw, 1, 2, &chunks::wdt::MPHDFlags::default()).unwrap();
+        let chunk = &adt.mcnk[0];
+        let quad = chunks::shared::CHUNK_SIZE / 8.0;
+
+        // The MCVT and MCNR offsets are the hole mask, so neither is read.
+        assert!(chunk.mcvt.is_none() && chunk.mcnr.is_none());
+        assert_eq!(chunk.holes_high_res(), Some(0x0100_0000_0000_0002));
+        assert!(chunk.is_hole(1000.5 - 0.5 * quad, 2000.25 - 1.5 * quad));
+        assert!(chunk.is_hole(1000.5 - 7.5 * quad, 2000.25 - 0.5 * quad));
+        assert!(!chunk.is_hole(1000.5 - 0.5 * quad, 2000.25 - 0.5 * quad));
+        assert_eq!(adt.height_at(1000.5 - 0.5 * quad, 2000.25 - 0.5 * quad), None);
+
+        let written = ADT::from_bytes(&adt.to_bytes().unwrap(), 1, 2, &chunks::wdt::MPHDFlags::default()).unwrap();
+        assert_eq!(written.mcnk[0].holes_high_res(), Some(0x0100_0000_0000_0002));
+        assert_eq!(written.mcnk[0].mcly.layers.len(), 1);
+    }
+
+    #[test]
+    fn terrain_height() {
+        let adt = ADT::from_bytes(&raw_adt(), 1, 2, &chunks::wdt::MPHDFlags::default()).unwrap();
+        let quad = chunks::shared::CHUNK_SIZE / 8.0;
+        let height = |i: usize| 50.0 + i as f32 * 0.37 - 12.5;
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-3;
+
+        // On the vertices, the heights are exact.
+        assert!(close(adt.height_at(1000.5, 2000.25).unwrap(), height(0)));
+        assert!(close(adt.height_at(1000.5 - 0.5 * quad, 2000.25 - 0.5 * quad).unwrap(), height(9)));
+        assert!(close(adt.height_at(1000.5 - 8.0 * quad, 2000.25 - 8.0 * quad).unwrap(), height(144)));
+
+        // Halfway along the north edge of the first quad, and halfway from its west edge to the centre.
+        assert!(close(adt.height_at(1000.5, 2000.25 - 0.5 * quad).unwrap(), (height(0) + height(1)) / 2.0));
+        let west = (height(0) + height(17)) / 2.0;
+        assert!(close(adt.height_at(1000.5 - 0.5 * quad, 2000.25 - 0.25 * quad).unwrap(), (west + height(9)) / 2.0));
+
+        assert_eq!(adt.height_at(1000.5 - 0.5 * quad, 2000.25 - 2.5 * quad), None);
+        assert_eq!(adt.height_at(1001.0, 2000.25), None);
+    }
+
+    #[test]
+    fn terrain_height_on_chunk_edges() {
+        let mut adt = ADT::from_bytes(&raw_adt(), 1, 2, &chunks::wdt::MPHDFlags::default()).unwrap();
+        let quad = chunks::shared::CHUNK_SIZE / 8.0;
+        let height = |i: usize| 50.0 + i as f32 * 0.37 - 12.5;
+
+        // A neighbour to the north that is all holes, sharing the north edge of the fixture's MCNK.
+        let mut north = adt.mcnk[0].clone();
+        north.position.x += chunks::shared::CHUNK_SIZE;
+        north.holes_low_res = 0xFFFF;
+        adt.mcnk.insert(0, north);
+
+        let on_edge = adt.height_at(1000.5, 2000.25 - 0.5 * quad).unwrap();
+        assert!((on_edge - (height(0) + height(1)) / 2.0).abs() < 1e-3);
+        assert_eq!(adt.height_at(1000.5 + quad, 2000.25 - 0.5 * quad), None);
+    }
+
+    #[test]
+    fn chunk_liquids() {
+        use binread::BinReaderExt;
+        use chunks::adt::{LiquidKind, LiquidTile};
+
+        // An ocean covering the whole chunk at (3, 5), with only its first tile fishable.
+        let mut mh2o = vec![0; 256 * 12];
+        let mut header = Vec::new();
+        push_u32s(&mut header, &[256 * 12, 1, 256 * 12 + 24]);
+        mh2o[83 * 12..84 * 12].copy_from_slice(&header);
+        mh2o.extend(2u16.to_le_bytes());
+        mh2o.extend(0u16.to_le_bytes());
+        for height in [100.0f32, 100.0] {
+            mh2o.extend(height.to_le_bytes());
+        }
+        mh2o.extend([0, 0, 8, 8]);
+        push_u32s(&mut mh2o, &[0, 0]);
+        mh2o.extend(1u64.to_le_bytes());
+        mh2o.extend(0u64.to_le_bytes());
+
+        let mut raw = raw_adt();
+        push_chunk(&mut raw, "MH2O", &mh2o);
+        let mut adt = ADT::from_bytes(&raw, 1, 2, &chunks::wdt::MPHDFlags::default()).unwrap();
+
+        // A magma MCLQ in the same chunk, which is only used without MH2O.
+        let mut mclq = Vec::new();
+        for height in [5.0f32, 6.0] {
+            mclq.extend(height.to_le_bytes());
+        }
+        for _ in 0..81 {
+            mclq.extend([0; 4]);
+            mclq.extend(5.5f32.to_le_bytes());
+        }
+        mclq.extend([0x03; 64]);
+        mclq.extend([0; 84]);
+        let flags = chunks::adt::MCNKFlags { lq_magma: true, ..Default::default() };
+        adt.mcnk[0].mclq = Cursor::new(&mclq).read_le_args((flags,)).unwrap();
+
+        let liquids = adt.chunk_liquids(&adt.mcnk[0]);
+        assert_eq!(liquids.len(), 1);
+        assert_eq!(liquids[0].kind, LiquidKind::Ocean);
+        assert_eq!(liquids[0].liquid_type, Some(2));
+        assert_eq!(liquids[0].heights[8][8], 100.0);
+        assert_eq!(liquids[0].tile(0, 0), Some(LiquidTile { fishable: Some(true), fatigue: false }));
+        assert_eq!(liquids[0].tile(7, 7), Some(LiquidTile { fishable: Some(false), fatigue: false }));
+
+        adt.mh2o = None;
+        let liquids = adt.chunk_liquids(&adt.mcnk[0]);
+        assert_eq!(liquids.len(), 1);
+        assert_eq!(liquids[0].kind, LiquidKind::Magma);
+        assert_eq!(liquids[0].liquid_type, None);
+        assert_eq!((liquids[0].min_height, liquids[0].max_height), (5.0, 6.0));
+        assert_eq!(liquids[0].heights[4][4], 5.5);
+    }
+
+    #[test]
+    fn follow_offsets() {
+        let mut raw = raw_adt();
+        let options = ParseOptions { follow_offsets: true, ..Default::default() };
+
+        let adt = ADT::from_reader_with_options(Cursor::new(&raw), 1, 2, &chunks::wdt::MPHDFlags::default(), &options).unwrap();
+        assert!(adt.mtex.is_some());
+        assert_eq!(adt.mcnk.len(), 1);
+        assert_eq!(adt.to_bytes().unwrap(), raw);
+
+        // Point the MTEX offset into the middle of the chunk.
+        let mtex = 12 + 8 + 4 + 4;
+        let offset = u32::from_le_bytes(raw[mtex..mtex + 4].try_into().unwrap());
+        raw[mtex..mtex + 4].copy_from_slice(&(offset + 4).to_le_bytes());
+
+        let error = ADT::from_reader_with_options(Cursor::new(&raw), 1, 2, &chunks::wdt::MPHDFlags::default(), &options).unwrap_err();
+        assert!(matches!(error, Error::InvalidOffset { expected: "MTEX", .. }));
+        assert_eq!(error.location().unwrap().tile, Some((1, 2)));
+    }
+
+    #[test]
+    fn join_textures() {
+        let adt = ADT {
+            mtex: Some(chunks::adt::MTEX { filenames: vec!["a.blp".to_string(), "b.blp".to_string()] }),
+            mtxf: Some(chunks::adt::MTXF { flags: vec![chunks::adt::MTXFFlags { do_not_load_specular_or_height: true, ..Default::default() }] }),
+            ..Default::default()
+        };
+
+        let textures = adt.textures();
+        assert_eq!(textures.len(), 2);
+        assert_eq!(textures[0].filename, "a.blp");
+        assert!(textures[0].flags.do_not_load_specular_or_height);
+        assert_eq!(textures[1].flags, chunks::adt::MTXFFlags::default());
+    }
+
+    #[test]
+    fn resolve_chunk_references() {
+        use binread::BinReaderExt;
+
+        let mut adt = ADT::from_bytes(&raw_adt(), 1, 2, &chunks::wdt::MPHDFlags::default()).unwrap();
+
+        let part = |size: usize, name_id: u32| {
+            let mut raw = vec![0; size];
+            raw[..4].copy_from_slice(&name_id.to_le_bytes());
+            Cursor::new(raw)
+        };
+
+        adt.mmdx = Some(chunks::adt::MMDX { filenames: vec!["a.m2".to_string(), "bb.m2".to_string()] });
+        adt.mmid = Some(chunks::adt::MMID { offsets: vec![0, 5] });
+        adt.mddf = Some(chunks::adt::MDDF { parts: vec![part(36, 1).read_le().unwrap(), part(36, 0).read_le().unwrap()] });
+        adt.mwmo = Some(chunks::shared::MWMO { filenames: vec!["c.wmo".to_string()] });
+        adt.mwid = Some(chunks::adt::MWID { offsets: vec![0, 3] });
+        adt.modf = Some(chunks::shared::MODF { parts: vec![part(64, 1).read_le().unwrap(), part(64, 0).read_le().unwrap()] });
+
+        let mut refs = Vec::new();
+        push_u32s(&mut refs, &[0, 7, 1, 1]);
+        adt.mcnk[0].mcrf = Cursor::new(refs).read_le_args((3, 1)).unwrap();
+
+        let chunk = &adt.mcnk[0];
+        let doodads = adt.chunk_doodads(chunk);
+        assert_eq!(doodads.len(), 2);
+        assert_eq!(doodads[0].filename, Some("bb.m2"));
+        assert_eq!(doodads[1].filename, Some("a.m2"));
+
+        // The second WMO's MWID offset doesn't start a filename.
+        let wmos = adt.chunk_wmos(chunk);
+        assert_eq!(wmos.len(), 1);
+        assert_eq!(wmos[0].placement.name_id, 0);
+        assert_eq!(wmos[0].filename, Some("c.wmo"));
+        assert_eq!(adt.wmo_filename(1), None);
+    }
+
+    #[test]
+    fn locate_parse_error() {
+        let mut raw = raw_adt();
+
+        // Point the MCNR of the first MCNK past the end of the chunk.
+        let mcnk = raw.len() - (raw_mcnk().len() + 8);
+        let ofs_normal = mcnk + 8 + 24;
+        raw[ofs_normal..ofs_normal + 4].copy_from_slice(&0xFFFFu32.to_le_bytes());
 
-        let adt = ADT::from_file(adt_path, &wdt.mphd.as_ref().and_then(|chunk| Some(&chunk.flags)).expect("WDT should have a valid MPHD chunk"))?;
+        let error = ADT::from_bytes(&raw, 1, 2, &chunks::wdt::MPHDFlags::default()).unwrap_err();
+        let location = error.location().unwrap();
 
-        Ok(adt) 
+        assert_eq!(location.tile, Some((1, 2)));
+        assert_eq!(location.chunk.as_deref(), Some("MCNK"));
+        assert_eq!(location.chunk_index, Some((0, 0)));
+        assert_eq!(location.subchunk.as_deref(), Some("MCNR"));
+        assert_eq!(location.offset, Some((mcnk + 0xFFFF) as u64));
     }
 }
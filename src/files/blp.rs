@@ -5,15 +5,6 @@ use binread::{BinRead, BinReaderExt, BinResult, ReadOptions};
 
 use crate::error::Error;
 
-#[derive(Debug, BinRead)]
-#[br(little)]
-pub struct BLPPixel {
-    pub b: u8,
-    pub g: u8,
-    pub r: u8,
-    _pad: u8,
-}
-
 #[derive(Clone, Copy, Debug, BinRead)]
 #[br(little, repr = u8)]
 pub enum AlphaCompression {
@@ -55,37 +46,32 @@ impl BinRead for Mipmap {
         args: Self::Args,
     ) -> BinResult<Self> {
         let (
-            layer,
+            _layer,
             color_encoding,
-            alpha_channel_bit_depth,
+            _alpha_channel_bit_depth,
             alpha_compression,
             width,
             height,
             buffer_size,
         ) = args;
 
+        let unsupported = || Error::UnsupportedBLPEncoding { color_encoding, alpha_compression };
+
         match color_encoding {
-            ColorEncoding::PALETTE => {
-                todo!("PALETTE encoding for BLPs is not implemented!");
-            },
             ColorEncoding::DXT => {
-                let format = match alpha_compression {
-                    AlphaCompression::DXT1 => texpresso::Format::Bc1,
-                    AlphaCompression::DXT3 => texpresso::Format::Bc2,
-                    AlphaCompression::DXT5 => texpresso::Format::Bc3,
-                    _ => panic!("Invalid alpha compression for DXT: {:?}", alpha_compression),
-                };
-
                 // Calculate the correct size of the compressed buffer.
-                let valid_size = match alpha_compression {
-                    AlphaCompression::DXT1 => ((width + 3) / 4) * ((height + 3) / 4) * 8,
-                    AlphaCompression::DXT3 | AlphaCompression::DXT5 => ((width + 3) / 4) * ((height + 3) / 4) * 16,
-                    _ => panic!("Invalid alpha compression for DXT: {:?}", alpha_compression),
+                let (format, block_size) = match alpha_compression {
+                    AlphaCompression::DXT1 => (texpresso::Format::Bc1, 8),
+                    AlphaCompression::DXT3 => (texpresso::Format::Bc2, 16),
+                    AlphaCompression::DXT5 => (texpresso::Format::Bc3, 16),
+                    _ => return Err(unsupported().into_binread(reader.stream_position()?)),
                 };
+                let valid_size = width.div_ceil(4) as usize * height.div_ceil(4) as usize * block_size;
 
-                let mut compressed = vec![0u8; valid_size as usize];
-                let mut handle = reader.take(buffer_size.into());
-                handle.read(&mut compressed)?;
+                // Short buffers are padded out, rather than failing to decode the whole mipmap.
+                let mut compressed = Vec::with_capacity(valid_size);
+                reader.take(buffer_size.into()).read_to_end(&mut compressed)?;
+                compressed.resize(valid_size, 0);
 
                 let width = width as usize;
                 let height = height as usize;
@@ -95,18 +81,17 @@ impl BinRead for Mipmap {
 
                 Ok(Self { decompressed })
             },
-            ColorEncoding::ARGB8888 | ColorEncoding::ARGB8888_=> {
-                todo!("ARGB encoding for BLPs is not implemented!");
+            ColorEncoding::JPEG | ColorEncoding::PALETTE | ColorEncoding::ARGB8888 | ColorEncoding::ARGB8888_ => {
+                Err(unsupported().into_binread(reader.stream_position()?))
             },
-            _ => panic!("Unsupported format: {:?}", color_encoding),
         }
     }
 }
 
 #[derive(Debug)]
 pub struct BLP {
-    magic: u32,
-    version: u32,
+    pub magic: u32,
+    pub version: u32,
     pub color_encoding: ColorEncoding,
     pub alpha_channel_bit_depth: u8,
     pub alpha_compression: AlphaCompression,
@@ -122,7 +107,7 @@ impl BinRead for BLP {
     fn read_options<R: Read + Seek>(
         reader: &mut R,
         _: &ReadOptions,
-        args: Self::Args,
+        _: Self::Args,
     ) -> BinResult<Self> {
         let magic: u32 = reader.read_le()?;
         let version: u32 = reader.read_le()?;
@@ -149,7 +134,7 @@ impl BinRead for BLP {
         let mips = &mip_offsets.iter().filter(|o| **o != 0).count();
 
         let mut mipmaps: Vec<Mipmap> = Vec::with_capacity(*mips);
-        for (i, (offset, size)) in zip(mip_offsets.into_iter(), mip_sizes.into_iter()).enumerate() {
+        for (i, (offset, size)) in zip(mip_offsets, mip_sizes).enumerate() {
             if offset != 0 && size != 0 {
                 reader.seek(SeekFrom::Start(offset.into()))?;
                 mipmaps.push(reader.read_le_args((i, color_encoding, alpha_channel_bit_depth, alpha_compression, width, height, size))?);
@@ -183,6 +168,55 @@ impl TryFrom<PathBuf> for BLP {
         let mut cursor = Cursor::new(file);
 
         let parsed_blp: Self = cursor.read_le()?;
-        return Ok(parsed_blp);
+        Ok(parsed_blp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw_blp(color_encoding: u8, alpha_compression: u8) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend(b"BLP2");
+        buf.extend(1u32.to_le_bytes());
+        buf.extend([color_encoding, 8, alpha_compression, 0]);
+        buf.extend(4u32.to_le_bytes());
+        buf.extend(4u32.to_le_bytes());
+
+        // A single 4x4 mipmap straight after the header.
+        let mut offsets = [0u32; 16];
+        let mut sizes = [0u32; 16];
+        offsets[0] = 148;
+        sizes[0] = 16;
+        for value in offsets.into_iter().chain(sizes) {
+            buf.extend(value.to_le_bytes());
+        }
+        buf.extend([0; 16]);
+
+        buf
+    }
+
+    fn parse(raw: Vec<u8>) -> Result<BLP, Error> {
+        Ok(Cursor::new(raw).read_le()?)
+    }
+
+    #[test]
+    fn decode_dxt() {
+        let blp = parse(raw_blp(2, 1)).unwrap();
+        assert_eq!(blp.mipmaps.len(), 1);
+        assert_eq!(blp.mipmaps[0].decompressed.len(), 4 * 4 * 4);
+    }
+
+    #[test]
+    fn reject_unsupported_encodings() {
+        for (color_encoding, alpha_compression) in [(1, 0), (3, 2), (2, 2)] {
+            match parse(raw_blp(color_encoding, alpha_compression)) {
+                Err(Error::UnsupportedBLPEncoding { color_encoding: c, alpha_compression: a }) => {
+                    assert_eq!((c as u8, a as u8), (color_encoding, alpha_compression));
+                },
+                other => panic!("expected UnsupportedBLPEncoding, got {:?}", other),
+            }
+        }
     }
 }
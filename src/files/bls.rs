@@ -5,10 +5,9 @@ use binread::{BinRead, BinReaderExt};
 
 use crate::error::Error;
 
-
-
 #[derive(BinRead, Debug)]
 #[br(repr = u32)]
+#[repr(u32)]
 pub enum ParamType
 {
     Vector4 = 0x0,            // C4Vector
@@ -29,12 +28,12 @@ pub enum ParamType
 pub struct BLSBlockParam {
     #[br(count = 64, map = |v: Vec<char>| v.into_iter().filter(|c| *c != '\0').collect())]
     pub name: String,
-    binding: u32,
+    pub binding: u32,
     #[br(count = 16)]
-    float: Vec<f32>,
-    param_type: ParamType,
-    unk: u32,
-    unk2: u32,
+    pub float: Vec<f32>,
+    pub param_type: ParamType,
+    pub unk: u32,
+    pub unk2: u32,
 }
 
 #[derive(BinRead, Debug)]
@@ -61,9 +60,9 @@ pub struct BLSBlock {
 #[derive(Debug)]
 pub struct BLS {
     pub token: String,
-    version: u32,
-    permutation_count: u32,
-    blocks: Vec<BLSBlock>,
+    pub version: u32,
+    pub permutation_count: u32,
+    pub blocks: Vec<BLSBlock>,
 }
 
 impl BinRead for BLS {
@@ -71,8 +70,8 @@ impl BinRead for BLS {
 
     fn read_options<R: std::io::Read + std::io::Seek>(
         reader: &mut R,
-        options: &binread::ReadOptions,
-        args: Self::Args,
+        _: &binread::ReadOptions,
+        _: Self::Args,
     ) -> binread::BinResult<Self> {
         let mut token: Vec<char> = Vec::new();
         for _ in 0..4 { token.push(reader.read_le()?) }
@@ -84,7 +83,7 @@ impl BinRead for BLS {
         let offsets_n = match token.as_str() {
             "SPXG" => 12,
             "SVXG" => 6,
-            _ => panic!("Found invalid shader type: {}", &token)
+            _ => return Err(Error::InvalidShaderType(token).into_binread(0)),
         };
 
         let mut offsets: Vec<u32> = Vec::new();
@@ -93,7 +92,6 @@ impl BinRead for BLS {
         let mut blocks: Vec<BLSBlock> = Vec::new();
         for (i, offset) in offsets.into_iter().enumerate() {
             if offset != 0 {
-                println!("Reading buffer {}", offset);
                 reader.seek(SeekFrom::Start(offset.into()))?;
                 blocks.push(reader.read_le_args((i,))?)
             }
@@ -117,7 +115,25 @@ impl TryFrom<PathBuf> for BLS {
         let mut cursor = Cursor::new(file);
 
         let parsed_blp: Self = cursor.read_le()?;
-        return Ok(parsed_blp);
+        Ok(parsed_blp)
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reject_unknown_shader_type() {
+        let mut raw = Vec::new();
+        raw.extend(b"SXXG");
+        raw.extend(1u32.to_le_bytes());
+        raw.extend(0u32.to_le_bytes());
+
+        let parsed: Result<BLS, Error> = Cursor::new(raw).read_le::<BLS>().map_err(Error::from);
+        match parsed {
+            Err(Error::InvalidShaderType(token)) => assert_eq!(token, "SXXG"),
+            other => panic!("expected InvalidShaderType, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,22 @@
+//! Helpers for building chunked files in tests.
+
+/// Appends a chunk whose header size is the length of its data, returning the offset of its header.
+pub(crate) fn push_chunk(buf: &mut Vec<u8>, token: &str, data: &[u8]) -> usize {
+    push_chunk_with_size(buf, token, data.len() as u32, data)
+}
+
+/// Appends a chunk with the given header size, for chunks whose header doesn't match their data (e.g. MCNR).
+pub(crate) fn push_chunk_with_size(buf: &mut Vec<u8>, token: &str, size: u32, data: &[u8]) -> usize {
+    let offset = buf.len();
+    buf.extend(token.bytes().rev());
+    buf.extend(size.to_le_bytes());
+    buf.extend(data);
+
+    offset
+}
+
+pub(crate) fn push_u32s(buf: &mut Vec<u8>, values: &[u32]) {
+    for value in values {
+        buf.extend(value.to_le_bytes());
+    }
+}
@@ -1,8 +1,8 @@
 #[macro_export]
 macro_rules! parse_chunk {
-    ( $chunk_type:ty, $data:expr, &mut $ref:expr) => {
+    ( $chunk_type:ty, $chunks:expr, $header:expr, &mut $ref:expr) => {
         {
-            let chunk = parse_chunk_data::<$chunk_type>($data)?;
+            let chunk = parse_chunk_located::<_, $chunk_type>(&mut $chunks, $header, ())?;
             $ref = Some(chunk);
         }
     };
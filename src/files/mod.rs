@@ -1,29 +1,132 @@
 //! Parsing logic and base structs for all chunked file formats.
-use binread::{BinReaderExt, io::Cursor};
+use std::io::{Read, Seek, Write};
 
-use crate::error::Error;
+use crate::chunks;
+use crate::chunks::shared::ChunkWrite;
+use crate::error::{Error, Location};
+use crate::version::ClientVersion;
 
 mod macros;
 mod adt;
 mod wdt;
 mod blp;
 mod bls;
+#[cfg(test)]
+mod fixtures;
 
-pub use adt::ADT;
-pub use wdt::WDT;
-pub use blp::BLP;
+pub use adt::{DoodadPlacement, LazyADT, WMOPlacement, ADT};
+pub use wdt::{TileBounds, WDT};
+pub use blp::{AlphaCompression, ColorEncoding, BLP};
 pub use bls::BLS;
 
-fn parse_chunk_data<T: binread::BinRead>(chunk_data: &Vec<u8>) -> Result<T, Error> {
-    let mut chunk_data_cursor = Cursor::new(chunk_data);
-    let chunk_data: T = chunk_data_cursor.read_le().map_err(Error::Unknown)?;
+/// How the parsers treat chunks with a token they do not recognise.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnknownChunkPolicy {
+    /// Skip over the chunk and discard it.
+    Ignore,
+    /// Keep the chunk as a [`chunks::shared::RawChunk`] on the parsed file.
+    #[default]
+    Keep,
+    /// Stop parsing and return [`Error::UnknownChunk`].
+    Error,
+}
+
+/// Options controlling how chunked files are parsed.
+#[derive(Clone, Debug, Default)]
+pub struct ParseOptions {
+    pub unknown_chunks: UnknownChunkPolicy,
+    /// The client version to parse for, or `None` to detect it from the file.
+    pub version: Option<ClientVersion>,
+    /// The earliest version a detected version can be, e.g. the version of the WDT an ADT belongs to.
+    /// Ignored if `version` is given.
+    pub min_version: Option<ClientVersion>,
+    /// Locate the ADT chunks through the MHDR and MCIN offsets instead of reading the file in order.
+    ///
+    /// This handles files with a non-standard chunk order and checks that the offsets are valid,
+    /// but skips any chunks that aren't referenced by the offsets.
+    pub follow_offsets: bool,
+    /// Looks up the vertex format of MH2O liquids, or `None` to guess it from the stock 3.3.5
+    /// liquid types with [`chunks::adt::LiquidVertexFormat::from_liquid_type`].
+    pub liquid_vertex_format: Option<chunks::adt::LiquidVertexFormatLookup>,
+}
 
-    Ok(chunk_data)
+// Minimum sizes of the fixed-size chunks, checked before parsing so that short chunks
+// are reported with their size rather than as a failed read.
+fn minimum_chunk_size(token: &str) -> Option<u32> {
+    match token {
+        "MVER" => Some(4),
+        "MHDR" => Some(64),
+        "MCIN" => Some(16 * 16 * 16),
+        "MCNK" => Some(128),
+        "MH2O" => Some(16 * 16 * 12),
+        "MFBO" => Some(36),
+        "MPHD" => Some(32),
+        "MAIN" => Some(64 * 64 * 8),
+        _ => None,
+    }
 }
 
-fn parse_chunk_data_args<T: binread::BinRead>(chunk_data: &Vec<u8>, args: T::Args) -> Result<T, Error> {
-    let mut chunk_data_cursor = Cursor::new(chunk_data);
-    let chunk_data: T = chunk_data_cursor.read_le_args(args).map_err(Error::Unknown)?;
+// Parses a chunk, attaching the chunk (and subchunk, if one failed) to any error.
+fn parse_chunk_located<R: Read + Seek, T: binread::BinRead>(
+    chunks: &mut chunks::shared::ChunkIter<R>,
+    header: &chunks::shared::ChunkHeader,
+    args: T::Args,
+) -> Result<T, Error> {
+    let mut location = Box::new(Location {
+        chunk: Some(header.token.clone()),
+        offset: Some(header.offset),
+        ..Default::default()
+    });
+
+    if let Some(expected) = minimum_chunk_size(&header.token) {
+        if header.size < expected {
+            return Err(Error::InvalidSize { location, expected: expected.into(), actual: header.size.into() });
+        }
+    }
+
+    chunks.parse_data(header, args).map_err(|source| match source {
+        binread::Error::Custom { err, pos } => match err.downcast::<chunks::shared::SubchunkError>() {
+            Ok(subchunk) => {
+                location.subchunk = Some(subchunk.token.to_string());
+                location.offset = Some(header.offset + u64::from(subchunk.offset));
+                Error::Parse { location, source: subchunk.source }
+            },
+            Err(err) => match err.downcast::<Error>() {
+                // Errors raised by the chunk itself only know their position within it.
+                Ok(error) => error.locate(|error_location| {
+                    error_location.chunk = location.chunk.take();
+                    error_location.offset = Some(header.offset);
+                }),
+                Err(err) => Error::Parse { location, source: binread::Error::Custom { err, pos } },
+            },
+        },
+        source => Error::Parse { location, source },
+    })
+}
+
+fn handle_unknown_chunk<R: Read + Seek>(
+    options: &ParseOptions,
+    chunks: &mut chunks::shared::ChunkIter<R>,
+    header: chunks::shared::ChunkHeader,
+    unknown_chunks: &mut Vec<chunks::shared::RawChunk>,
+) -> Result<(), Error> {
+    match options.unknown_chunks {
+        UnknownChunkPolicy::Ignore => {},
+        UnknownChunkPolicy::Keep => unknown_chunks.push(chunks::shared::RawChunk {
+            data: chunks.read_data(&header)?,
+            token: header.token,
+            offset: header.offset,
+        }),
+        UnknownChunkPolicy::Error => return Err(Error::UnknownChunk { token: header.token, offset: header.offset }),
+    };
+
+    Ok(())
+}
 
-    Ok(chunk_data)
+// Writes a chunk if it is present, returning the offset it was written at.
+fn write_optional_chunk<W: Write + Seek, T: ChunkWrite>(writer: &mut W, token: &str, chunk: &Option<T>) -> Result<Option<u64>, Error> {
+    match chunk {
+        Some(chunk) => Ok(Some(chunks::shared::write_chunk(writer, token, &chunk.to_bytes()?)?)),
+        None => Ok(None),
+    }
 }
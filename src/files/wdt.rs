@@ -1,61 +1,262 @@
 use std::fs::File;
+use std::io::{Cursor, Read, Seek, Write};
 use std::path::PathBuf;
 
-use binread::BinReaderExt;
-
 use crate::error::Error;
 use crate::files::macros;
+use crate::version::ClientVersion;
 
 use crate::chunks;
 
-use super::parse_chunk_data;
+use super::{handle_unknown_chunk, parse_chunk_located, write_optional_chunk, ParseOptions};
 
 #[derive(Clone, Debug, Default)]
 pub struct WDT {
     pub filename: String,
     pub path: PathBuf,
+    /// The client version the WDT was parsed as, either given in [`ParseOptions`] or detected.
+    pub version: ClientVersion,
 
     pub mver: Option<chunks::shared::MVER>,
     pub mphd: Option<chunks::wdt::MPHD>,
     pub main: Option<chunks::wdt::MAIN>,
     pub mwmo: Option<chunks::shared::MWMO>,
     pub modf: Option<chunks::shared::MODF>,
+
+    /// Chunks that weren't recognised, kept according to [`super::UnknownChunkPolicy`].
+    pub unknown_chunks: Vec<chunks::shared::RawChunk>,
+}
+
+/// An inclusive rectangle of ADT tile coordinates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TileBounds {
+    pub min_x: u32,
+    pub min_y: u32,
+    pub max_x: u32,
+    pub max_y: u32,
+}
+
+impl TileBounds {
+    /// Number of tiles along the x axis.
+    pub fn width(&self) -> u32 {
+        self.max_x - self.min_x + 1
+    }
+
+    /// Number of tiles along the y axis.
+    pub fn height(&self) -> u32 {
+        self.max_y - self.min_y + 1
+    }
 }
 
-pub fn parse_wdt_file(path: PathBuf) -> Result<WDT, Error> {
+pub fn parse_wdt_file(path: PathBuf, options: &ParseOptions) -> Result<WDT, Error> {
     let filename = path.file_name().ok_or(Error::InvalidFilename(path.clone()))?
         .to_string_lossy().to_string();
 
-    let mut file = File::open(&path)?;
+    let file = File::open(&path)?;
+    let parsed_wdt = parse_wdt(file, options)
+        .map_err(|e| e.locate(|location| location.path = Some(path.clone())))?;
 
-    let mut parsed_wdt = WDT {
+    Ok(WDT {
         filename,
         path,
-        ..Default::default()
-    };
-
-    loop {
-        if let Some(chunk_wrapper) = file.read_le::<chunks::shared::ChunkWrapper>().ok() {
-            match chunk_wrapper.token.as_str() {
-                "MVER" => macros::parse_chunk!(chunks::shared::MVER, &chunk_wrapper.data, &mut parsed_wdt.mver),
-                "MPHD" => macros::parse_chunk!(chunks::wdt::MPHD, &chunk_wrapper.data, &mut parsed_wdt.mphd),
-                "MAIN" => macros::parse_chunk!(chunks::wdt::MAIN, &chunk_wrapper.data, &mut parsed_wdt.main),
-                "MWMO" => macros::parse_chunk!(chunks::shared::MWMO, &chunk_wrapper.data, &mut parsed_wdt.mwmo),
-                "MODF" => macros::parse_chunk!(chunks::shared::MODF, &chunk_wrapper.data, &mut parsed_wdt.modf),
-                _ => panic!("Unknown chunk type {}!", chunk_wrapper.token),
-            };
-        } else {
-            return Ok(parsed_wdt);
-        }
+        ..parsed_wdt
+    })
+}
+
+fn parse_wdt<R: Read + Seek>(reader: R, options: &ParseOptions) -> Result<WDT, Error> {
+    let mut parsed_wdt = WDT::default();
+
+    let mut chunks = chunks::shared::ChunkIter::new(reader)?;
+    while let Some(header) = chunks.next() {
+        let header = header?;
+        match header.token.as_str() {
+            "MVER" => macros::parse_chunk!(chunks::shared::MVER, chunks, &header, &mut parsed_wdt.mver),
+            "MPHD" => macros::parse_chunk!(chunks::wdt::MPHD, chunks, &header, &mut parsed_wdt.mphd),
+            "MAIN" => macros::parse_chunk!(chunks::wdt::MAIN, chunks, &header, &mut parsed_wdt.main),
+            "MWMO" => macros::parse_chunk!(chunks::shared::MWMO, chunks, &header, &mut parsed_wdt.mwmo),
+            "MODF" => macros::parse_chunk!(chunks::shared::MODF, chunks, &header, &mut parsed_wdt.modf),
+            _ => handle_unknown_chunk(options, &mut chunks, header, &mut parsed_wdt.unknown_chunks)?,
+        };
     }
+
+    parsed_wdt.version = options.version
+        .unwrap_or_else(|| ClientVersion::detect_wdt(parsed_wdt.mphd.as_ref()));
+
+    Ok(parsed_wdt)
 }
 
 impl WDT {
     pub fn from_file(path: PathBuf) -> Result<Self, Error> {
+        Self::from_file_with_options(path, &ParseOptions::default())
+    }
+
+    pub fn from_file_with_options(path: PathBuf, options: &ParseOptions) -> Result<Self, Error> {
         if !path.exists() {
             return Err(Error::FileNotFound(path))
         }
 
-        Ok(parse_wdt_file(path)?) 
+        parse_wdt_file(path, options)
+    }
+
+    /// Parses a WDT from any seekable source, such as a file extracted from an archive.
+    pub fn from_reader<R: Read + Seek>(reader: R) -> Result<Self, Error> {
+        Self::from_reader_with_options(reader, &ParseOptions::default())
+    }
+
+    pub fn from_reader_with_options<R: Read + Seek>(reader: R, options: &ParseOptions) -> Result<Self, Error> {
+        parse_wdt(reader, options)
+    }
+
+    /// Parses a WDT from an in-memory buffer.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Self::from_reader(Cursor::new(bytes))
+    }
+
+    /// The (x, y) coordinates and flags of every tile that has an ADT, row by row.
+    pub fn tiles(&self) -> impl Iterator<Item = (u32, u32, chunks::wdt::MAINTileFlags)> + '_ {
+        self.main.iter()
+            .flat_map(|main| main.tiles.iter().enumerate())
+            .filter(|(_, tile)| tile.flags.has_adt)
+            .map(|(i, tile)| (i as u32 % 64, i as u32 / 64, tile.flags))
+    }
+
+    /// Whether the map has an ADT at (x, y).
+    pub fn has_tile(&self, x: u32, y: u32) -> bool {
+        self.main.as_ref()
+            .and_then(|main| main.tile(x, y))
+            .is_some_and(|tile| tile.flags.has_adt)
+    }
+
+    /// The smallest rectangle containing every tile with an ADT, or `None` if there are none.
+    pub fn tile_bounds(&self) -> Option<TileBounds> {
+        self.tiles().fold(None, |bounds, (x, y, _)| Some(match bounds {
+            None => TileBounds { min_x: x, min_y: y, max_x: x, max_y: y },
+            Some(bounds) => TileBounds {
+                min_x: bounds.min_x.min(x),
+                min_y: bounds.min_y.min(y),
+                max_x: bounds.max_x.max(x),
+                max_y: bounds.max_y.max(y),
+            },
+        }))
+    }
+
+    /// The terrain height at a world position, loading the ADT of the tile it is in from next to the WDT.
+    ///
+    /// Returns `None` if the map has no ADT there, or the position is in a hole. As the ADT is read
+    /// on every call, load it with [`super::ADT::from_wdt`] for repeated queries on the same tile.
+    pub fn height_at(&self, x: f32, y: f32) -> Result<Option<f32>, Error> {
+        let Some((tile_x, tile_y)) = chunks::shared::tile_at(x, y) else { return Ok(None) };
+        if !self.has_tile(tile_x, tile_y) {
+            return Ok(None);
+        }
+
+        Ok(super::ADT::from_wdt(self, tile_x, tile_y)?.height_at(x, y))
+    }
+
+    /// Serialises the WDT, followed by any unknown chunks that were kept while parsing.
+    pub fn write_to<W: Write + Seek>(&self, writer: &mut W) -> Result<(), Error> {
+        write_optional_chunk(writer, "MVER", &self.mver)?;
+        write_optional_chunk(writer, "MPHD", &self.mphd)?;
+        write_optional_chunk(writer, "MAIN", &self.main)?;
+        write_optional_chunk(writer, "MWMO", &self.mwmo)?;
+        write_optional_chunk(writer, "MODF", &self.modf)?;
+
+        for chunk in self.unknown_chunks.iter() {
+            chunks::shared::write_chunk(writer, &chunk.token, &chunk.data)?;
+        }
+
+        Ok(())
+    }
+
+    /// Serialises the WDT into a new buffer, see [`WDT::write_to`].
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut cursor = Cursor::new(Vec::new());
+        self.write_to(&mut cursor)?;
+
+        Ok(cursor.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::files::fixtures::push_chunk;
+
+    fn raw_wdt() -> Vec<u8> {
+        let mut buf = Vec::new();
+        push_chunk(&mut buf, "MVER", &18u32.to_le_bytes());
+
+        let mut mphd = vec![0; 32];
+        mphd[0] = 0x84;
+        push_chunk(&mut buf, "MPHD", &mphd);
+
+        let mut main = vec![0; 4096 * 8];
+        main[(30 * 64 + 31) * 8] = 1;
+        main[(30 * 64 + 32) * 8] = 3;
+        main[(34 * 64 + 29) * 8] = 1;
+        push_chunk(&mut buf, "MAIN", &main);
+
+        push_chunk(&mut buf, "MWMO", b"World\\wmo\\Test.wmo\0");
+
+        let mut modf: Vec<u8> = Vec::new();
+        for value in [0u32, 7] {
+            modf.extend(value.to_le_bytes());
+        }
+        for value in [1.0f32, 2.0, 3.0, 0.0, 90.0, 0.0, -1.0, -2.0, -3.0, 4.0, 5.0, 6.0] {
+            modf.extend(value.to_le_bytes());
+        }
+        for value in [1u16, 2, 0, 0] {
+            modf.extend(value.to_le_bytes());
+        }
+        push_chunk(&mut buf, "MODF", &modf);
+
+        buf
+    }
+
+    #[test]
+    fn enumerate_tiles() {
+        let wdt = WDT::from_bytes(&raw_wdt()).unwrap();
+
+        let tiles: Vec<_> = wdt.tiles().map(|(x, y, flags)| (x, y, flags.all_water)).collect();
+        assert_eq!(tiles, vec![(31, 30, false), (32, 30, true), (29, 34, false)]);
+        assert!(wdt.has_tile(32, 30));
+        assert!(!wdt.has_tile(30, 32));
+        assert!(!wdt.has_tile(64, 0));
+
+        let bounds = wdt.tile_bounds().unwrap();
+        assert_eq!(bounds, TileBounds { min_x: 29, min_y: 30, max_x: 32, max_y: 34 });
+        assert_eq!((bounds.width(), bounds.height()), (4, 5));
+    }
+
+    #[test]
+    fn write_round_trip() {
+        let raw = raw_wdt();
+        let wdt = WDT::from_bytes(&raw).unwrap();
+
+        let flags = &wdt.mphd.as_ref().unwrap().flags;
+        assert!(flags.adt_has_big_alpha && flags.adt_has_height_texturing && !flags.adt_has_mccv);
+
+        let modf = wdt.modf.as_ref().unwrap();
+        assert_eq!(modf.parts.len(), 1);
+        assert_eq!(modf.parts[0].doodat_set, 2);
+        assert_eq!(wdt.to_bytes().unwrap(), raw);
+    }
+
+    #[test]
+    fn require_mphd_for_adts() {
+        let mut raw = Vec::new();
+        push_chunk(&mut raw, "MVER", &18u32.to_le_bytes());
+        push_chunk(&mut raw, "MAIN", &vec![0; 4096 * 8]);
+
+        let mut wdt = WDT::from_bytes(&raw).unwrap();
+        wdt.path = PathBuf::from("World/Maps/Test/Test.wdt");
+
+        match crate::files::ADT::from_wdt(&wdt, 31, 30) {
+            Err(Error::MissingChunk { location, token }) => {
+                assert_eq!(token, "MPHD");
+                assert_eq!(location.path, Some(wdt.path.clone()));
+            },
+            other => panic!("expected MissingChunk, got {:?}", other.map(|_| ())),
+        }
     }
 }
@@ -15,13 +15,13 @@
 //! -----------
 //!
 //! 
-//! ```rust
+//! ```no_run
 //! // Load and parse the ADT at (25, 20) in the Azeroth map.
 //! let wdt_path = std::path::PathBuf::from("./test_data/Azeroth/Azeroth.wdt");
 //! let adt = wow_chunky::files::ADT::from_wdt_file(wdt_path, 25, 20);
 //! ```
 //! 
-//! ```rust
+//! ```no_run
 //! // Load and parse the WDT first.
 //! let wdt_path = std::path::PathBuf::from("./test_data/Azeroth/Azeroth.wdt");
 //! let wdt = wow_chunky::files::WDT::from_file(wdt_path).expect("Invalid WDT file");
@@ -30,7 +30,40 @@
 //! // parse the ADT at (31, 30).
 //! let adt = wow_chunky::files::ADT::from_wdt(&wdt, 31, 30);
 //! ```
+//!
+//! ```no_run
+//! // Parse files that are already in memory (e.g. extracted from an archive).
+//! // The ADT coordinates have to be given explicitly, as there is no filename to take them from.
+//! let wdt_bytes = std::fs::read("./test_data/Azeroth/Azeroth.wdt").unwrap();
+//! let wdt = wow_chunky::files::WDT::from_bytes(&wdt_bytes).expect("Invalid WDT file");
+//!
+//! let adt_bytes = std::fs::read("./test_data/Azeroth/Azeroth_31_30.adt").unwrap();
+//! let flags = &wdt.mphd.as_ref().expect("WDT should have a MPHD chunk").flags;
+//! let adt = wow_chunky::files::ADT::from_bytes(&adt_bytes, 31, 30, flags);
+//! ```
+//!
+//! ```no_run
+//! // Only decode the MCNKs that are needed.
+//! let wdt_path = std::path::PathBuf::from("./test_data/Azeroth/Azeroth.wdt");
+//! let wdt = wow_chunky::files::WDT::from_file(wdt_path).expect("Invalid WDT file");
+//! let flags = &wdt.mphd.as_ref().expect("WDT should have a MPHD chunk").flags;
+//!
+//! let adt_path = std::path::PathBuf::from("./test_data/Azeroth/Azeroth_31_30.adt");
+//! let mut adt = wow_chunky::files::LazyADT::from_file(adt_path, flags).unwrap();
+//! let mcnk = adt.chunk(8, 8).unwrap();
+//! ```
+//!
+//! ```no_run
+//! // Edit an ADT and write it back out.
+//! let wdt_path = std::path::PathBuf::from("./test_data/Azeroth/Azeroth.wdt");
+//! let mut adt = wow_chunky::files::ADT::from_wdt_file(wdt_path, 31, 30).unwrap();
+//! adt.mcnk[0].area_id = 12;
+//!
+//! let mut file = std::fs::File::create("./Azeroth_31_30.adt").unwrap();
+//! adt.write_to(&mut file).unwrap();
+//! ```
 
 pub mod chunks;
 pub mod files;
-pub mod error;
\ No newline at end of file
+pub mod error;
+pub mod version;
\ No newline at end of file
@@ -0,0 +1,67 @@
+//! Client versions targeted by the parsers, for the layouts that differ between expansions.
+use crate::chunks;
+
+/// A World of Warcraft client version.
+///
+/// Layouts that changed between versions are parsed according to this:
+///
+/// - the MHDR offsets that exist ([`crate::chunks::adt::MHDR::tokens`]),
+/// - whether the MCNK MCCV offset is read (3.x) or is padding,
+/// - whether an empty MCLQ is skipped (3.x, where liquids are in MH2O),
+/// - the MCSE sound emitter layout, when it can't be told from the subchunk size.
+///
+/// BLP files use the same BLP2 layout in every supported version, so BLP parsing doesn't take a version.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ClientVersion {
+    /// 1.12.x
+    #[default]
+    Vanilla,
+    /// 2.4.3
+    TBC,
+    /// 3.3.5a
+    WotLK,
+}
+
+impl ClientVersion {
+    /// Guesses the version an ADT was written for from the chunk tokens it contains.
+    ///
+    /// Every supported version uses MVER 18 and mostly the same chunks, so this is the earliest
+    /// version that has all of the chunks present. A 3.3.5 tile without any water, flight bounds or
+    /// texture flags can't be told apart from a 1.12 one.
+    pub fn detect_adt<'a>(tokens: impl IntoIterator<Item = &'a str>) -> Self {
+        tokens.into_iter()
+            .map(|token| match token {
+                "MH2O" | "MTXF" => ClientVersion::WotLK,
+                "MFBO" => ClientVersion::TBC,
+                _ => ClientVersion::Vanilla,
+            })
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Guesses the version a WDT was written for from its MPHD flags.
+    ///
+    /// Like [`ClientVersion::detect_adt`], this is only a lower bound: the earliest version that could
+    /// have written the flags. No MPHD flags were added in 2.4.3, so this only returns
+    /// [`ClientVersion::Vanilla`] or [`ClientVersion::WotLK`], and every 2.4.3 WDT is detected as 1.12.
+    /// ADTs loaded through [`crate::files::ADT::from_wdt`] use it as [`crate::files::ParseOptions::min_version`],
+    /// so their own chunks decide the version from there.
+    pub fn detect_wdt(mphd: Option<&chunks::wdt::MPHD>) -> Self {
+        match mphd {
+            Some(mphd) if mphd.flags.has_wotlk_flags() => ClientVersion::WotLK,
+            _ => ClientVersion::Vanilla,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_adt_version() {
+        assert_eq!(ClientVersion::detect_adt(["MVER", "MHDR", "MCNK"]), ClientVersion::Vanilla);
+        assert_eq!(ClientVersion::detect_adt(["MVER", "MHDR", "MCNK", "MFBO"]), ClientVersion::TBC);
+        assert_eq!(ClientVersion::detect_adt(["MVER", "MH2O", "MCNK", "MFBO"]), ClientVersion::WotLK);
+    }
+}